@@ -1,16 +1,27 @@
 use anchor_lang::prelude::*;
 use anchor_spl::token::{self, Mint, Token, TokenAccount};
+use anchor_lang::solana_program::ed25519_program;
 use anchor_lang::solana_program::hash::hash;
+use anchor_lang::solana_program::sysvar::instructions::{
+    load_current_index_checked, load_instruction_at_checked,
+};
 
 declare_id!("Apsj9Xp8EEpAoZLv5tzgpFa2B9wCeCTmVmR8UiQvieQx");
 
+pub const SETTLE_DELAY_SLOTS: u64 = 2; // bet outcome comes from a slot hash this far ahead
+pub const CLAIM_VOUCHER_TTL: i64 = 300; // seconds a signed claim voucher stays valid
+pub const BPS_DENOMINATOR: u64 = 10000;
+pub const DEFAULT_MAX_RTP_BPS: u16 = 9500; // 95% return-to-player ceiling
+pub const MAX_FEE_SPLITS: usize = 4;
+
 #[program]
 pub mod instant_lottery {
     use super::*;
 
-    pub fn initialize(ctx: Context<Initialize>, min_bet: u64) -> Result<()> {
+    pub fn initialize(ctx: Context<Initialize>, lottery_id: u64, min_bet: u64) -> Result<()> {
         let lottery = &mut ctx.accounts.lottery;
         lottery.authority = ctx.accounts.authority.key();
+        lottery.lottery_id = lottery_id;
         lottery.token_mint = ctx.accounts.token_mint.key();
         lottery.min_bet = min_bet;
         lottery.locked = false;
@@ -20,10 +31,20 @@ pub mod instant_lottery {
         lottery.pool_amount = 0;
         lottery.play_times = 0;
         lottery.prize_amount = 0;
+        lottery.max_rtp_bps = DEFAULT_MAX_RTP_BPS;
+        lottery.fee_splits = Vec::new();
+        Ok(())
+    }
+
+    pub fn register_player(ctx: Context<RegisterPlayer>) -> Result<()> {
+        let player_account = &mut ctx.accounts.player_account;
+        player_account.player = ctx.accounts.player.key();
+        player_account.unclaimed_prize = 0;
+        player_account.unclaimed_fee = 0;
         Ok(())
     }
 
-    pub fn play(ctx: Context<Play>, amount: u64, uuid: String) -> Result<()> {
+    pub fn play(ctx: Context<Play>, amount: u64) -> Result<()> {
         let lottery = &mut ctx.accounts.lottery;
         require!(!lottery.locked, LotteryError::LotteryLocked);
         require!(amount >= lottery.min_bet, LotteryError::BetTooSmall);
@@ -40,57 +61,115 @@ pub mod instant_lottery {
             amount,
         )?;
 
+        // Partner fees are held aside for sweeping and never enter the prize pool.
+        let pool_share = accrue_fees(&mut lottery.fee_splits, amount)
+            .and_then(|fees| amount.checked_sub(fees))
+            .ok_or(LotteryError::ArithmeticOverflow)?;
+
         lottery.pool_amount = lottery
             .pool_amount
-            .checked_add(amount)
+            .checked_add(pool_share)
+            .ok_or(LotteryError::ArithmeticOverflow)?;
+
+        let bet = &mut ctx.accounts.bet;
+        bet.player = ctx.accounts.player.key();
+        bet.amount = amount;
+        bet.play_id = lottery.play_times;
+        bet.target_slot = Clock::get()?
+            .slot
+            .checked_add(SETTLE_DELAY_SLOTS)
             .ok_or(LotteryError::ArithmeticOverflow)?;
+        // Settle against the paytable in force now, not whatever it is later.
+        bet.total_weight = lottery.total_weight;
+        bet.weight_ranges = lottery.weight_ranges;
+        bet.multipliers = lottery.multipliers;
 
         lottery.play_times = lottery
             .play_times
             .checked_add(1)
             .ok_or(LotteryError::ArithmeticOverflow)?;
 
-        let mut random_seed = ctx.accounts.recent_blockhashes.key().to_bytes().to_vec();
-        random_seed.extend_from_slice(&ctx.accounts.player.key().to_bytes());
-        random_seed.extend_from_slice(&Clock::get()?.slot.to_le_bytes());
-        random_seed.extend_from_slice(&Clock::get()?.unix_timestamp.to_le_bytes());
-        random_seed.extend_from_slice(uuid.as_bytes());
-
-        let hash = hash(&random_seed);
-        let hash_bytes = hash.to_bytes();
-
-        let numbers: [u8; 3] = (0..3)
-            .map(|i| {
-                let slice = &hash_bytes[i * 8..(i + 1) * 8];
-                let random =
-                    u64::from_le_bytes(slice.try_into().unwrap()) % lottery.total_weight as u64;
-                get_number(random, &lottery.weight_ranges)
-            }).collect::<Vec<_>>()
-            .try_into()
-            .unwrap();
-
-        let win_multiplier = if numbers.windows(2).all(|w| w[0] == w[1]) {
-            lottery.multipliers[(numbers[0] - 1) as usize]
-        } else {
-            0
-        };
+        emit!(BetPlaced {
+            player: bet.player,
+            amount,
+            play_id: bet.play_id,
+            target_slot: bet.target_slot,
+        });
+
+        Ok(())
+    }
+
+    pub fn settle(ctx: Context<Settle>) -> Result<()> {
+        let lottery_key = ctx.accounts.lottery.key();
+        let lottery = &mut ctx.accounts.lottery;
+        let bet = &ctx.accounts.bet;
+
+        require!(
+            Clock::get()?.slot > bet.target_slot,
+            LotteryError::BetNotReady
+        );
+
+        // A bet whose slot hash has aged out of the sysvar settles as a loss,
+        // otherwise a player could wait out losing outcomes.
+        let slot_hash = find_settle_slot_hash(
+            &ctx.accounts.slot_hashes.try_borrow_data()?,
+            bet.target_slot,
+        );
+
+        let mut numbers = [0u8; 3];
+        let mut win_multiplier = 0;
+
+        if let Some(slot_hash) = slot_hash {
+            let mut random_seed = slot_hash.to_vec();
+            random_seed.extend_from_slice(&lottery_key.to_bytes());
+            random_seed.extend_from_slice(&bet.player.to_bytes());
+            random_seed.extend_from_slice(&bet.play_id.to_le_bytes());
+
+            let hash_bytes = hash(&random_seed).to_bytes();
+
+            numbers = (0..3)
+                .map(|i| {
+                    let slice = &hash_bytes[i * 8..(i + 1) * 8];
+                    let random =
+                        u64::from_le_bytes(slice.try_into().unwrap()) % bet.total_weight as u64;
+                    get_number(random, &bet.weight_ranges)
+                })
+                .collect::<Vec<_>>()
+                .try_into()
+                .unwrap();
+
+            if numbers.windows(2).all(|w| w[0] == w[1]) {
+                win_multiplier = bet.multipliers[(numbers[0] - 1) as usize];
+            }
+        }
 
         if win_multiplier > 0 {
-            let total_prize = amount
+            let prize = bet
+                .amount
                 .checked_mul(win_multiplier as u64)
-                .and_then(|x| x.checked_mul(101))
-                .and_then(|x| x.checked_div(100))
+                .ok_or(LotteryError::ArithmeticOverflow)?;
+            let fee = prize / 100;
+
+            let player_account = &mut ctx.accounts.player_account;
+            player_account.unclaimed_prize = player_account
+                .unclaimed_prize
+                .checked_add(prize)
+                .ok_or(LotteryError::ArithmeticOverflow)?;
+            player_account.unclaimed_fee = player_account
+                .unclaimed_fee
+                .checked_add(fee)
                 .ok_or(LotteryError::ArithmeticOverflow)?;
 
             lottery.prize_amount = lottery
                 .prize_amount
-                .checked_add(total_prize)
+                .checked_add(prize)
+                .and_then(|x| x.checked_add(fee))
                 .ok_or(LotteryError::ArithmeticOverflow)?;
         }
 
         emit!(PlayEvent {
-            player: ctx.accounts.player.key(),
-            amount,
+            player: bet.player,
+            amount: bet.amount,
             numbers,
             win_multiplier,
         });
@@ -98,35 +177,54 @@ pub mod instant_lottery {
         Ok(())
     }
 
-    pub fn claim_prize(
-        ctx: Context<ClaimPrize>,
-        prize_amount: u64,
-        fee_amount: u64,
-        timestamp: i64,
-    ) -> Result<()> {
+    pub fn claim_prize(ctx: Context<ClaimPrize>, nonce: [u8; 8]) -> Result<()> {
+        let message =
+            load_claim_message(&ctx.accounts.instructions, &ctx.accounts.lottery.authority)?;
+
         require!(
-            ctx.accounts.lottery.prize_amount > 0,
-            LotteryError::InsufficientPrize
+            message.lottery == ctx.accounts.lottery.key()
+                && message.player == ctx.accounts.player.key()
+                && message.nonce == nonce,
+            LotteryError::InvalidSignature
         );
 
         let clock = Clock::get()?;
         require!(
-            clock.unix_timestamp - timestamp < 300,
+            clock.unix_timestamp - message.timestamp < CLAIM_VOUCHER_TTL,
             LotteryError::SignatureExpired
         );
 
+        let prize_amount = message.prize_amount;
+        let fee_amount = message.fee_amount;
+
         let total_amount = prize_amount
             .checked_add(fee_amount)
             .ok_or(LotteryError::ArithmeticOverflow)?;
 
+        require!(total_amount > 0, LotteryError::InsufficientPrize);
+        require!(
+            prize_amount <= ctx.accounts.player_account.unclaimed_prize
+                && fee_amount <= ctx.accounts.player_account.unclaimed_fee,
+            LotteryError::InsufficientPrize
+        );
         require!(
             ctx.accounts.lottery.prize_amount >= total_amount,
             LotteryError::InsufficientPrize
         );
 
+        let claim_nonce = &mut ctx.accounts.claim_nonce;
+        claim_nonce.player = message.player;
+        claim_nonce.timestamp = clock.unix_timestamp;
+
         let auth_key = ctx.accounts.lottery.authority;
         let authority_ref = auth_key.as_ref();
-        let signer_seeds = &[b"lottery" as &[u8], authority_ref, &[ctx.bumps.lottery]];
+        let lottery_id = ctx.accounts.lottery.lottery_id.to_le_bytes();
+        let signer_seeds = &[
+            b"lottery" as &[u8],
+            authority_ref,
+            &lottery_id,
+            &[ctx.bumps.lottery],
+        ];
 
         token::transfer(
             CpiContext::new_with_signer(
@@ -154,6 +252,16 @@ pub mod instant_lottery {
             fee_amount,
         )?;
 
+        let player_account = &mut ctx.accounts.player_account;
+        player_account.unclaimed_prize = player_account
+            .unclaimed_prize
+            .checked_sub(prize_amount)
+            .ok_or(LotteryError::ArithmeticOverflow)?;
+        player_account.unclaimed_fee = player_account
+            .unclaimed_fee
+            .checked_sub(fee_amount)
+            .ok_or(LotteryError::ArithmeticOverflow)?;
+
         let lottery = &mut ctx.accounts.lottery;
         lottery.pool_amount = lottery
             .pool_amount
@@ -182,6 +290,7 @@ pub mod instant_lottery {
         total_weight: Option<u32>,
         weight_ranges: Option<[u32; 5]>,
         multipliers: Option<[u8; 5]>,
+        max_rtp_bps: Option<u16>,
     ) -> Result<()> {
         let lottery = &mut ctx.accounts.lottery;
         
@@ -194,30 +303,155 @@ pub mod instant_lottery {
             });
         }
         
-        if let Some(weight) = total_weight {
-            lottery.total_weight = weight;
+        if total_weight.is_none()
+            && weight_ranges.is_none()
+            && multipliers.is_none()
+            && max_rtp_bps.is_none()
+        {
+            return Ok(());
         }
-        
-        if let Some(ranges) = weight_ranges {
-            lottery.weight_ranges = ranges;
+
+        let total_weight = total_weight.unwrap_or(lottery.total_weight);
+        let weight_ranges = weight_ranges.unwrap_or(lottery.weight_ranges);
+        let multipliers = multipliers.unwrap_or(lottery.multipliers);
+        let max_rtp_bps = max_rtp_bps.unwrap_or(lottery.max_rtp_bps);
+
+        require!(
+            max_rtp_bps as u64 <= BPS_DENOMINATOR,
+            LotteryError::RtpTooHigh
+        );
+        require!(
+            max_rtp_bps as u64 + lottery.fee_bps() <= BPS_DENOMINATOR,
+            LotteryError::FeeSplitTooHigh
+        );
+        let rtp_bps = expected_rtp_bps(total_weight, &weight_ranges, &multipliers)?;
+        require!(rtp_bps <= max_rtp_bps as u64, LotteryError::RtpTooHigh);
+
+        lottery.total_weight = total_weight;
+        lottery.weight_ranges = weight_ranges;
+        lottery.multipliers = multipliers;
+        lottery.max_rtp_bps = max_rtp_bps;
+
+        emit!(PaytableUpdated {
+            total_weight,
+            weight_ranges,
+            multipliers,
+            rtp_bps,
+            max_rtp_bps,
+        });
+
+        Ok(())
+    }
+
+    pub fn update_fee_splits(
+        ctx: Context<AdminAction>,
+        fee_splits: Vec<FeeSplitInfo>,
+    ) -> Result<()> {
+        let lottery = &mut ctx.accounts.lottery;
+        require!(
+            fee_splits.len() <= MAX_FEE_SPLITS,
+            LotteryError::TooManyFeeSplits
+        );
+
+        let mut recipients = std::collections::HashSet::new();
+        let mut fee_bps: u64 = 0;
+        for split in fee_splits.iter() {
+            require!(
+                split.bps > 0 && recipients.insert(split.recipient),
+                LotteryError::InvalidFeeSplit
+            );
+            fee_bps += split.bps as u64;
         }
-        
-        if let Some(mults) = multipliers {
-            lottery.multipliers = mults;
+        require!(
+            lottery.max_rtp_bps as u64 + fee_bps <= BPS_DENOMINATOR,
+            LotteryError::FeeSplitTooHigh
+        );
+
+        let released_fees = lottery
+            .replace_fee_splits(&fee_splits)
+            .ok_or(LotteryError::ArithmeticOverflow)?;
+
+        emit!(FeeSplitsUpdated {
+            fee_splits,
+            released_fees,
+        });
+
+        Ok(())
+    }
+
+    pub fn sweep_fees<'info>(ctx: Context<'_, '_, '_, 'info, SweepFees<'info>>) -> Result<()> {
+        let fee_splits = ctx.accounts.lottery.fee_splits.clone();
+
+        // One recipient token account per fee split, in table order.
+        require!(
+            ctx.remaining_accounts.len() == fee_splits.len(),
+            LotteryError::InvalidFeeRecipient
+        );
+
+        let auth_key = ctx.accounts.lottery.authority;
+        let authority_ref = auth_key.as_ref();
+        let lottery_id = ctx.accounts.lottery.lottery_id.to_le_bytes();
+        let signer_seeds = &[
+            b"lottery" as &[u8],
+            authority_ref,
+            &lottery_id,
+            &[ctx.bumps.lottery],
+        ];
+
+        for (split, recipient_account) in fee_splits.iter().zip(ctx.remaining_accounts.iter()) {
+            require!(
+                recipient_account.key() == split.recipient && recipient_account.owner == &token::ID,
+                LotteryError::InvalidFeeRecipient
+            );
+            if split.accrued == 0 {
+                continue;
+            }
+
+            let recipient_token_account =
+                TokenAccount::try_deserialize(&mut &recipient_account.try_borrow_data()?[..])
+                    .map_err(|_| LotteryError::InvalidFeeRecipient)?;
+            require!(
+                recipient_token_account.mint == ctx.accounts.lottery.token_mint,
+                LotteryError::InvalidFeeRecipient
+            );
+
+            token::transfer(
+                CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info(),
+                    token::Transfer {
+                        from: ctx.accounts.token_account.to_account_info(),
+                        to: recipient_account.clone(),
+                        authority: ctx.accounts.lottery.to_account_info(),
+                    },
+                    &[signer_seeds],
+                ),
+                split.accrued,
+            )?;
+        }
+
+        let lottery = &mut ctx.accounts.lottery;
+        for split in lottery.fee_splits.iter_mut() {
+            split.accrued = 0;
         }
 
+        emit!(FeesSwept {
+            recipients: fee_splits.iter().map(|split| split.recipient).collect(),
+            amounts: fee_splits.iter().map(|split| split.accrued).collect(),
+        });
+
         Ok(())
     }
 }
 
 #[derive(Accounts)]
-#[instruction(min_bet: u64)]
+#[instruction(lottery_id: u64)]
 pub struct Initialize<'info> {
     #[account(
         init,
         payer = authority,
-        space = 8 + 32 + 32 + 8 + 1 + 4 + 20 + 5 + 8 + 8 + 8, 
-        seeds = [b"lottery", authority.key().as_ref()],
+        space = 8 + 32 + 8 + 32 + 8 + 1 + 4 + 20 + 5 + 8 + 8 + 8 + 2
+            + 4 + MAX_FEE_SPLITS * (32 + 2 + 8),
+        seeds = [b"lottery", authority.key().as_ref(), &lottery_id.to_le_bytes()],
         bump
     )]
     pub lottery: Account<'info, Lottery>,
@@ -227,7 +461,7 @@ pub struct Initialize<'info> {
     #[account(
         init,
         payer = authority,
-        seeds = [b"token_account", authority.key().as_ref()],
+        seeds = [b"token_account", authority.key().as_ref(), &lottery_id.to_le_bytes()],
         bump,
         token::mint = token_mint,
         token::authority = lottery,
@@ -242,18 +476,41 @@ pub struct Initialize<'info> {
     pub rent: Sysvar<'info, Rent>,
 }
 
+#[derive(Accounts)]
+pub struct RegisterPlayer<'info> {
+    #[account(
+        seeds = [b"lottery", lottery.authority.as_ref(), &lottery.lottery_id.to_le_bytes()],
+        bump
+    )]
+    pub lottery: Account<'info, Lottery>,
+
+    #[account(
+        init,
+        payer = player,
+        space = 8 + 32 + 8 + 8,
+        seeds = [b"player", lottery.key().as_ref(), player.key().as_ref()],
+        bump
+    )]
+    pub player_account: Account<'info, PlayerAccount>,
+
+    #[account(mut)]
+    pub player: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
 #[derive(Accounts)]
 pub struct Play<'info> {
     #[account(
         mut,
-        seeds = [b"lottery", lottery.authority.as_ref()],
+        seeds = [b"lottery", lottery.authority.as_ref(), &lottery.lottery_id.to_le_bytes()],
         bump
     )]
     pub lottery: Account<'info, Lottery>,
 
     #[account(
         mut,
-        seeds = [b"token_account", lottery.authority.as_ref()],
+        seeds = [b"token_account", lottery.authority.as_ref(), &lottery.lottery_id.to_le_bytes()],
         bump,
     )]
     pub token_account: Account<'info, TokenAccount>,
@@ -265,34 +522,85 @@ pub struct Play<'info> {
     )]
     pub player_token: Account<'info, TokenAccount>,
 
+    #[account(
+        init,
+        payer = player,
+        space = 8 + 32 + 8 + 8 + 8 + 4 + 20 + 5,
+        seeds = [b"bet", lottery.key().as_ref(), player.key().as_ref()],
+        bump
+    )]
+    pub bet: Account<'info, Bet>,
+
+    #[account(
+        seeds = [b"player", lottery.key().as_ref(), player.key().as_ref()],
+        bump,
+        has_one = player
+    )]
+    pub player_account: Account<'info, PlayerAccount>,
+
     #[account(mut)]
     pub player: Signer<'info>,
 
-    /// CHECK: Recent blockhashes is used for randomness
-    pub recent_blockhashes: AccountInfo<'info>,
-
+    pub system_program: Program<'info, System>,
     pub token_program: Program<'info, Token>,
 }
 
 #[derive(Accounts)]
+pub struct Settle<'info> {
+    #[account(
+        mut,
+        seeds = [b"lottery", lottery.authority.as_ref(), &lottery.lottery_id.to_le_bytes()],
+        bump
+    )]
+    pub lottery: Account<'info, Lottery>,
+
+    #[account(
+        mut,
+        close = player,
+        seeds = [b"bet", lottery.key().as_ref(), bet.player.as_ref()],
+        bump,
+        has_one = player
+    )]
+    pub bet: Account<'info, Bet>,
+
+    #[account(
+        mut,
+        seeds = [b"player", lottery.key().as_ref(), bet.player.as_ref()],
+        bump
+    )]
+    pub player_account: Account<'info, PlayerAccount>,
+
+    /// CHECK: Bet owner receiving the bet account rent, checked by has_one
+    #[account(mut)]
+    pub player: AccountInfo<'info>,
+
+    /// CHECK: SlotHashes sysvar used for randomness
+    #[account(address = solana_program::sysvar::slot_hashes::ID)]
+    pub slot_hashes: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(nonce: [u8; 8])]
 pub struct ClaimPrize<'info> {
     #[account(
         mut,
-        seeds = [b"lottery", lottery.authority.as_ref()],
+        seeds = [b"lottery", lottery.authority.as_ref(), &lottery.lottery_id.to_le_bytes()],
         bump
     )]
     pub lottery: Account<'info, Lottery>,
 
     #[account(
-        signer, 
-        constraint = authority.key() == lottery.authority @ LotteryError::InvalidAuthority
+        init,
+        payer = player,
+        space = 8 + 32 + 8,
+        seeds = [b"nonce", lottery.key().as_ref(), nonce.as_ref()],
+        bump
     )]
-    /// CHECK: Authority signer
-    pub authority: AccountInfo<'info>,
+    pub claim_nonce: Account<'info, ClaimNonce>,
 
     #[account(
         mut,
-        seeds = [b"token_account", lottery.authority.as_ref()],
+        seeds = [b"token_account", lottery.authority.as_ref(), &lottery.lottery_id.to_le_bytes()],
         bump
     )]
     pub token_account: Account<'info, TokenAccount>,
@@ -306,12 +614,27 @@ pub struct ClaimPrize<'info> {
 
     #[account(
         mut,
-        constraint = dev_token.mint == lottery.token_mint
+        constraint = dev_token.mint == lottery.token_mint,
+        constraint = dev_token.owner == lottery.authority @ LotteryError::InvalidAuthority
     )]
     pub dev_token: Account<'info, TokenAccount>,
 
-    #[account(signer)]
+    #[account(
+        mut,
+        seeds = [b"player", lottery.key().as_ref(), player.key().as_ref()],
+        bump,
+        has_one = player
+    )]
+    pub player_account: Account<'info, PlayerAccount>,
+
+    #[account(mut)]
     pub player: Signer<'info>,
+
+    /// CHECK: Instructions sysvar holding the Ed25519 voucher verification
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions: AccountInfo<'info>,
+
+    pub system_program: Program<'info, System>,
     pub token_program: Program<'info, Token>,
 }
 
@@ -319,7 +642,7 @@ pub struct ClaimPrize<'info> {
 pub struct AdminAction<'info> {
     #[account(
         mut,
-        seeds = [b"lottery", lottery.authority.as_ref()],
+        seeds = [b"lottery", lottery.authority.as_ref(), &lottery.lottery_id.to_le_bytes()],
         bump,
         constraint = lottery.authority == authority.key()
     )]
@@ -327,10 +650,32 @@ pub struct AdminAction<'info> {
     pub authority: Signer<'info>,
 }
 
+#[derive(Accounts)]
+pub struct SweepFees<'info> {
+    #[account(
+        mut,
+        seeds = [b"lottery", lottery.authority.as_ref(), &lottery.lottery_id.to_le_bytes()],
+        bump
+    )]
+    pub lottery: Account<'info, Lottery>,
+
+    #[account(
+        mut,
+        seeds = [b"token_account", lottery.authority.as_ref(), &lottery.lottery_id.to_le_bytes()],
+        bump,
+        token::mint = lottery.token_mint,
+        token::authority = lottery
+    )]
+    pub token_account: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+}
+
 #[account]
 #[derive(Default, PartialEq)]
 pub struct Lottery {
     pub authority: Pubkey,
+    pub lottery_id: u64,
     pub token_mint: Pubkey,
     pub min_bet: u64,
     pub locked: bool,
@@ -340,10 +685,74 @@ pub struct Lottery {
     pub pool_amount: u64,
     pub play_times: u64,
     pub prize_amount: u64,
+    pub max_rtp_bps: u16,
+    pub fee_splits: Vec<FeeSplit>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default, PartialEq)]
+pub struct FeeSplit {
+    pub recipient: Pubkey,
+    pub bps: u16,
+    pub accrued: u64,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct FeeSplitInfo {
+    pub recipient: Pubkey,
+    pub bps: u16,
+}
+
+impl Lottery {
+    pub fn fee_bps(&self) -> u64 {
+        self.fee_splits.iter().map(|split| split.bps as u64).sum()
+    }
+
+    // Installs a new fee table. Fees a dropped recipient never swept go back to
+    // the prize pool, so a dead recipient account cannot pin the table.
+    pub fn replace_fee_splits(&mut self, fee_splits: &[FeeSplitInfo]) -> Option<u64> {
+        let (splits, released) = carry_over_fees(&self.fee_splits, fee_splits)?;
+        self.pool_amount = self.pool_amount.checked_add(released)?;
+        self.fee_splits = splits;
+        Some(released)
+    }
+}
+
+impl FeeSplit {
+    pub fn fee(&self, amount: u64) -> u64 {
+        (amount as u128 * self.bps as u128 / BPS_DENOMINATOR as u128) as u64
+    }
+}
+
+#[account]
+#[derive(Default)]
+pub struct PlayerAccount {
+    pub player: Pubkey,
+    pub unclaimed_prize: u64,
+    pub unclaimed_fee: u64,
+}
+
+#[account]
+#[derive(Default)]
+pub struct Bet {
+    pub player: Pubkey,
+    pub amount: u64,
+    pub play_id: u64,
+    pub target_slot: u64,
+    pub total_weight: u32,
+    pub weight_ranges: [u32; 5],
+    pub multipliers: [u8; 5],
+}
+
+#[account]
+#[derive(Default)]
+pub struct ClaimNonce {
+    pub player: Pubkey,
+    pub timestamp: i64,
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize)]
 pub struct ClaimMessage {
+    pub lottery: Pubkey,
     pub player: Pubkey,
     pub prize_amount: u64,
     pub fee_amount: u64,
@@ -367,6 +776,28 @@ pub enum LotteryError {
     InvalidAuthority,
     #[msg("Insufficient prize amount available")]
     InsufficientPrize,
+    #[msg("Bet cannot be settled until its target slot has passed")]
+    BetNotReady,
+    #[msg("Invalid paytable: ranges must be non-decreasing and end at total weight")]
+    InvalidPaytable,
+    #[msg("Paytable return-to-player exceeds the configured maximum")]
+    RtpTooHigh,
+    #[msg("At most 4 fee splits are allowed")]
+    TooManyFeeSplits,
+    #[msg("Fee splits need a non-zero share and distinct recipients")]
+    InvalidFeeSplit,
+    #[msg("Fee splits plus the maximum return-to-player exceed 100%")]
+    FeeSplitTooHigh,
+    #[msg("Fee recipient account does not match the fee split table")]
+    InvalidFeeRecipient,
+}
+
+#[event]
+pub struct BetPlaced {
+    pub player: Pubkey,
+    pub amount: u64,
+    pub play_id: u64,
+    pub target_slot: u64,
 }
 
 #[event]
@@ -383,6 +814,15 @@ pub struct PauseStatusEvent {
     pub timestamp: i64,
 }
 
+#[event]
+pub struct PaytableUpdated {
+    pub total_weight: u32,
+    pub weight_ranges: [u32; 5],
+    pub multipliers: [u8; 5],
+    pub rtp_bps: u64,
+    pub max_rtp_bps: u16,
+}
+
 #[event]
 pub struct ClaimEvent {
     pub player: Pubkey,
@@ -390,6 +830,150 @@ pub struct ClaimEvent {
     pub actual_fee: u64,
 }
 
+#[event]
+pub struct FeeSplitsUpdated {
+    pub fee_splits: Vec<FeeSplitInfo>,
+    pub released_fees: u64,
+}
+
+#[event]
+pub struct FeesSwept {
+    pub recipients: Vec<Pubkey>,
+    pub amounts: Vec<u64>,
+}
+
+// Adds each recipient's cut of `amount` to its balance and returns the total.
+fn accrue_fees(fee_splits: &mut [FeeSplit], amount: u64) -> Option<u64> {
+    fee_splits.iter_mut().try_fold(0u64, |total, split| {
+        let fee = split.fee(amount);
+        split.accrued = split.accrued.checked_add(fee)?;
+        total.checked_add(fee)
+    })
+}
+
+// Builds the new fee table, carrying over what kept recipients are still owed,
+// and returns the unswept total of recipients that were dropped.
+fn carry_over_fees(
+    current: &[FeeSplit],
+    fee_splits: &[FeeSplitInfo],
+) -> Option<(Vec<FeeSplit>, u64)> {
+    let accrued = |recipient: Pubkey| {
+        current
+            .iter()
+            .find(|split| split.recipient == recipient)
+            .map(|split| split.accrued)
+    };
+    let splits = fee_splits
+        .iter()
+        .map(|split| FeeSplit {
+            recipient: split.recipient,
+            bps: split.bps,
+            accrued: accrued(split.recipient).unwrap_or(0),
+        })
+        .collect();
+    let released = current
+        .iter()
+        .filter(|split| {
+            fee_splits
+                .iter()
+                .all(|kept| kept.recipient != split.recipient)
+        })
+        .try_fold(0u64, |total, split| total.checked_add(split.accrued))?;
+    Some((splits, released))
+}
+
+fn load_claim_message(instructions: &AccountInfo, authority: &Pubkey) -> Result<ClaimMessage> {
+    // The voucher must be verified by an Ed25519 program instruction placed
+    // right before this one, carrying its signature, key and message inline.
+    let current_index = load_current_index_checked(instructions)?;
+    require!(current_index > 0, LotteryError::InvalidSignature);
+
+    let ix = load_instruction_at_checked((current_index - 1) as usize, instructions)?;
+    require!(
+        ix.program_id == ed25519_program::ID && ix.accounts.is_empty(),
+        LotteryError::InvalidSignature
+    );
+
+    let data = &ix.data;
+    require!(
+        data.len() >= 16 && data[0] == 1,
+        LotteryError::InvalidSignature
+    );
+
+    let read_u16 = |offset: usize| u16::from_le_bytes([data[offset], data[offset + 1]]);
+    require!(
+        read_u16(4) == u16::MAX && read_u16(8) == u16::MAX && read_u16(14) == u16::MAX,
+        LotteryError::InvalidSignature
+    );
+
+    let public_key_offset = read_u16(6) as usize;
+    let message_offset = read_u16(10) as usize;
+    let message_size = read_u16(12) as usize;
+
+    let public_key = data
+        .get(public_key_offset..public_key_offset + 32)
+        .ok_or(LotteryError::InvalidSignature)?;
+    require!(
+        public_key == authority.as_ref(),
+        LotteryError::InvalidAuthority
+    );
+
+    let message = data
+        .get(message_offset..message_offset + message_size)
+        .ok_or(LotteryError::InvalidSignature)?;
+    ClaimMessage::try_from_slice(message).map_err(|_| error!(LotteryError::InvalidSignature))
+}
+
+fn find_settle_slot_hash(slot_hashes: &[u8], target_slot: u64) -> Option<[u8; 32]> {
+    let count = u64::from_le_bytes(slot_hashes.get(..8)?.try_into().ok()?) as usize;
+    let mut found = None;
+
+    // Entries are newest first; use the earliest recorded slot at or after the
+    // target, and only once an older entry proves nothing in between was evicted.
+    for entry in slot_hashes[8..].chunks_exact(40).take(count) {
+        let slot = u64::from_le_bytes(entry[..8].try_into().unwrap());
+        if slot < target_slot {
+            return found;
+        }
+        found = Some(entry[8..].try_into().unwrap());
+        if slot == target_slot {
+            return found;
+        }
+    }
+
+    None
+}
+
+fn expected_rtp_bps(
+    total_weight: u32,
+    weight_ranges: &[u32; 5],
+    multipliers: &[u8; 5],
+) -> Result<u64> {
+    require!(
+        total_weight > 0 && weight_ranges[4] == total_weight,
+        LotteryError::InvalidPaytable
+    );
+    require!(
+        weight_ranges.windows(2).all(|w| w[0] <= w[1]),
+        LotteryError::InvalidPaytable
+    );
+
+    // A spin pays only when all three reels land on the same symbol, so each
+    // symbol contributes p^3 * multiplier to the expected return.
+    let total_weight = total_weight as u128;
+    let mut previous = 0u128;
+    let mut weighted_return = 0u128;
+    for (&range, &multiplier) in weight_ranges.iter().zip(multipliers.iter()) {
+        let weight = range as u128 - previous;
+        weighted_return += weight * weight * weight * multiplier as u128;
+        previous = range as u128;
+    }
+
+    let rtp_bps =
+        weighted_return * BPS_DENOMINATOR as u128 / (total_weight * total_weight * total_weight);
+    u64::try_from(rtp_bps).map_err(|_| error!(LotteryError::ArithmeticOverflow))
+}
+
 fn get_number(random: u64, weight_ranges: &[u32; 5]) -> u8 {
     for (i, &range) in weight_ranges.iter().enumerate() {
         if random < range as u64 {
@@ -398,3 +982,76 @@ fn get_number(random: u64, weight_ranges: &[u32; 5]) -> u8 {
     }
     5
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_paytable_rtp() {
+        // 4660^3 * 2 + 2967^3 * 5 + 1231^3 * 10 + 720^3 * 50 + 422^3 * 100 over 10000^3.
+        let rtp = expected_rtp_bps(
+            10000,
+            &[4660, 7627, 8858, 9578, 10000],
+            &[2, 5, 10, 50, 100],
+        );
+        assert_eq!(rtp.unwrap(), 3778);
+    }
+
+    #[test]
+    fn single_symbol_reels_return_the_multiplier() {
+        let rtp = expected_rtp_bps(100, &[100, 100, 100, 100, 100], &[1, 5, 10, 50, 100]);
+        assert_eq!(rtp.unwrap(), BPS_DENOMINATOR);
+
+        let rtp = expected_rtp_bps(100, &[0, 0, 0, 0, 100], &[0, 0, 0, 0, 3]);
+        assert_eq!(rtp.unwrap(), 3 * BPS_DENOMINATOR);
+    }
+
+    #[test]
+    fn inconsistent_ranges_are_rejected() {
+        let multipliers = [2, 5, 10, 50, 100];
+        assert!(expected_rtp_bps(0, &[0, 0, 0, 0, 0], &multipliers).is_err());
+        assert!(expected_rtp_bps(10000, &[4660, 7627, 8858, 9578, 9999], &multipliers).is_err());
+        assert!(expected_rtp_bps(10000, &[4660, 4000, 8858, 9578, 10000], &multipliers).is_err());
+    }
+
+    #[test]
+    fn reels_map_onto_weight_ranges() {
+        let weight_ranges = [4660, 7627, 8858, 9578, 10000];
+        assert_eq!(get_number(0, &weight_ranges), 1);
+        assert_eq!(get_number(4659, &weight_ranges), 1);
+        assert_eq!(get_number(4660, &weight_ranges), 2);
+        assert_eq!(get_number(9578, &weight_ranges), 5);
+        assert_eq!(get_number(9999, &weight_ranges), 5);
+    }
+
+    #[test]
+    fn dropped_fee_recipients_release_into_the_pool() {
+        let (kept, dropped) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let mut lottery = Lottery {
+            pool_amount: 1000,
+            fee_splits: vec![
+                FeeSplit {
+                    recipient: kept,
+                    bps: 100,
+                    accrued: 30,
+                },
+                FeeSplit {
+                    recipient: dropped,
+                    bps: 100,
+                    accrued: 20,
+                },
+            ],
+            ..Lottery::default()
+        };
+
+        let released = lottery.replace_fee_splits(&[FeeSplitInfo {
+            recipient: kept,
+            bps: 200,
+        }]);
+        assert_eq!(released, Some(20));
+        assert_eq!(lottery.pool_amount, 1020);
+        assert_eq!(lottery.fee_splits.len(), 1);
+        assert_eq!(lottery.fee_splits[0].accrued, 30);
+    }
+}
@@ -1,13 +1,26 @@
 use anchor_lang::prelude::*;
-use anchor_lang::solana_program::hash::hash;
+use anchor_lang::solana_program::hash::{hash, hashv};
+use anchor_spl::associated_token::{self, AssociatedToken};
 use anchor_spl::token::{self, Token, TokenAccount, Transfer};
 use solana_program::clock::Clock;
 
 declare_id!("4hHb7msxJiSY52LToCS1vvQd4friFRQkKyuK74HhNPgv");
 
 pub const LOCK_DURATION: i64 = 600; // 10 minutes lock period
-pub const DRAW_START_TIME: i64 = 0; // UTC 00:00:00
-pub const DRAW_END_TIME: i64 = 600; // UTC 00:10:00
+pub const SECONDS_PER_DAY: i64 = 86400;
+pub const MAX_SKIPPED_DAYS: usize = 32; // holiday list capacity
+pub const PRIZE_TIERS: usize = 6; // 6+1, 6+0, 5+1, 5+0/4+1, 4+0/3+1, 2+1/1+1/0+1
+pub const BPS_DENOMINATOR: u64 = 10000;
+pub const MAX_FEE_SPLITS: usize = 4;
+pub const DEFAULT_REVENUE_SPLIT: RevenueSplit = RevenueSplit {
+    prize_bps: BPS_DENOMINATOR as u16,
+    reserve_bps: 0,
+    house_bps: 0,
+};
+pub const MAX_COMPOUND_REDS: usize = 20; // C(20,6) = 38760 lines
+pub const MAX_COMPOUND_BLUES: usize = 16;
+pub const MAX_LINES_PER_TICKET: usize = 32;
+pub const DRAW_DELAY_SLOTS: u64 = 2; // slots between lock_draw and the slot hash mixed into the draw
 
 #[program]
 pub mod lottery_contract {
@@ -15,11 +28,17 @@ pub mod lottery_contract {
 
     pub fn initialize(
         ctx: Context<Initialize>,
+        lottery_id: u64,
         min_purchase_amount: u32,
         token_mint: Pubkey,
+        schedule: DrawSchedule,
     ) -> Result<()> {
+        require!(schedule.is_valid(), CustomError::InvalidSchedule);
+        require!(min_purchase_amount > 0, CustomError::InvalidPurchaseAmount);
+
         let lottery = &mut ctx.accounts.lottery;
         lottery.authority = ctx.accounts.authority.key();
+        lottery.lottery_id = lottery_id;
         lottery.token_account = ctx.accounts.token_account.key();
         lottery.token_mint = token_mint;
         lottery.last_draw_time = 0;
@@ -27,10 +46,39 @@ pub mod lottery_contract {
         lottery.min_purchase_amount = min_purchase_amount;
         lottery.last_draw_numbers = [0; 7];
         lottery.last_prize_amount = 0;
+        lottery.current_round = 0;
+        lottery.ticket_count = 0;
+        lottery.prize_tiers = [PrizeTier::default(); PRIZE_TIERS];
+        lottery.jackpot_pool = 0;
+        lottery.next_round_jackpot = 0;
+        lottery.jackpot_share_bps = 0;
+        lottery.min_jackpot = 0;
+        lottery.reserve_pool = 0;
+        lottery.revenue_split = DEFAULT_REVENUE_SPLIT;
+        lottery.house_revenue = 0;
+        lottery.fee_splits = Vec::new();
+        lottery.finalized_rounds = 0;
+        lottery.schedule = schedule;
+        lottery.skipped_days = Vec::new();
+        lottery.next_round_ticket_count = 0;
+        lottery.next_round_amount = 0;
+        lottery.open_prize_pool = 0;
+        lottery.next_round_prize_pool = 0;
+
+        let round = &mut ctx.accounts.round;
+        round.lottery = lottery.key();
+        round.round_id = 0;
+        round.start_time = Clock::get()?.unix_timestamp;
+        round.prize_tiers = lottery.prize_tiers;
         Ok(())
     }
 
-    pub fn buy_ticket(ctx: Context<BuyTicket>, numbers: [u8; 7], amount: u64) -> Result<()> {
+    pub fn buy_ticket(
+        ctx: Context<BuyTicket>,
+        numbers: [u8; 7],
+        amount: u64,
+        quick_pick: bool,
+    ) -> Result<()> {
         let clock = Clock::get()?;
         let lottery = &mut ctx.accounts.lottery;
 
@@ -40,21 +88,34 @@ pub mod lottery_contract {
             CustomError::InvalidTokenMint
         );
 
-        if lottery.is_locked {
-            let time_since_last_draw = clock.unix_timestamp - lottery.last_draw_time;
-            require!(
-                time_since_last_draw > LOCK_DURATION,
-                CustomError::LotteryLocked
-            );
-            lottery.is_locked = false;
-        }
+        lottery.open_sales(clock.unix_timestamp)?;
 
         require!(
             amount >= lottery.min_purchase_amount as u64,
             CustomError::InsufficientAmount
         );
+        // Only whole ticket units are sold, so no part of the payment goes unplayed.
+        require!(
+            amount.checked_rem(lottery.min_purchase_amount as u64) == Some(0),
+            CustomError::InvalidPurchaseAmount
+        );
+
+        let numbers = if quick_pick {
+            convert_random_to_numbers(&quick_pick_seed(
+                &lottery.key(),
+                lottery.ticket_count,
+                &ctx.accounts.buyer.key(),
+                &clock,
+                0,
+            ))
+        } else {
+            numbers
+        };
+
+        let reds = numbers[..6].to_vec();
+        let blues = vec![numbers[6]];
         require!(
-            validate_ticket_numbers(&numbers),
+            validate_selection(&reds, &blues),
             CustomError::InvalidTicketNumbers
         );
 
@@ -68,46 +129,328 @@ pub mod lottery_contract {
         );
         token::transfer(transfer_ctx, amount)?;
 
+        let units = amount
+            .checked_div(lottery.min_purchase_amount as u64)
+            .ok_or(CustomError::ArithmeticError)?;
+
+        let ticket = &mut ctx.accounts.ticket;
+        ticket.buyer = ctx.accounts.buyer.key();
+        ticket.lines = vec![TicketLine { reds, blues, units }];
+        enter_ticket(
+            lottery,
+            &mut ctx.accounts.round,
+            ticket,
+            amount,
+            clock.unix_timestamp,
+        )?;
+
         emit!(TicketPurchased {
-            buyer: ctx.accounts.buyer.key(),
+            buyer: ticket.buyer,
+            round_id: ticket.round_id,
+            ticket_id: ticket.ticket_id,
             numbers,
             amount,
+            quick_pick,
         });
 
         Ok(())
     }
 
-    pub fn draw(ctx: Context<Draw>, uuid: String) -> Result<()> {
+    pub fn buy_compound_ticket(
+        ctx: Context<BuyCompoundTicket>,
+        reds: Vec<u8>,
+        blues: Vec<u8>,
+    ) -> Result<()> {
         let clock = Clock::get()?;
-        let current_timestamp = clock.unix_timestamp;
-        let day_start = (current_timestamp / 86400) * 86400;
-        let seconds_from_day_start = current_timestamp - day_start;
+        let lottery = &mut ctx.accounts.lottery;
+
+        require!(
+            ctx.accounts.lottery_token_account.mint == lottery.token_mint
+                && ctx.accounts.buyer_token_account.mint == lottery.token_mint,
+            CustomError::InvalidTokenMint
+        );
+
+        lottery.open_sales(clock.unix_timestamp)?;
 
         require!(
-            seconds_from_day_start >= DRAW_START_TIME && seconds_from_day_start <= DRAW_END_TIME,
-            CustomError::InvalidDrawTime
+            validate_selection(&reds, &blues),
+            CustomError::InvalidTicketNumbers
+        );
+
+        let lines = compound_lines(&reds, &blues).ok_or(CustomError::ArithmeticError)?;
+        let amount = lines
+            .checked_mul(lottery.min_purchase_amount as u64)
+            .ok_or(CustomError::ArithmeticError)?;
+
+        let transfer_ctx = CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.buyer_token_account.to_account_info(),
+                to: ctx.accounts.lottery_token_account.to_account_info(),
+                authority: ctx.accounts.buyer.to_account_info(),
+            },
         );
+        token::transfer(transfer_ctx, amount)?;
+
+        let ticket = &mut ctx.accounts.ticket;
+        ticket.buyer = ctx.accounts.buyer.key();
+        ticket.lines = vec![TicketLine {
+            reds: reds.clone(),
+            blues: blues.clone(),
+            units: 1,
+        }];
+        enter_ticket(
+            lottery,
+            &mut ctx.accounts.round,
+            ticket,
+            amount,
+            clock.unix_timestamp,
+        )?;
 
+        emit!(CompoundTicketPurchased {
+            buyer: ticket.buyer,
+            round_id: ticket.round_id,
+            ticket_id: ticket.ticket_id,
+            reds,
+            blues,
+            lines,
+            amount,
+        });
+
+        Ok(())
+    }
+
+    pub fn buy_tickets(ctx: Context<BuyTickets>, lines: Vec<LineInfo>) -> Result<()> {
+        let clock = Clock::get()?;
         let lottery = &mut ctx.accounts.lottery;
 
-        require!(!lottery.is_locked, CustomError::AlreadyDrawn);
+        require!(
+            ctx.accounts.lottery_token_account.mint == lottery.token_mint
+                && ctx.accounts.buyer_token_account.mint == lottery.token_mint,
+            CustomError::InvalidTokenMint
+        );
+
+        lottery.open_sales(clock.unix_timestamp)?;
+
+        require!(
+            !lines.is_empty() && lines.len() <= MAX_LINES_PER_TICKET,
+            CustomError::InvalidLineCount
+        );
+
+        let mut lines = lines;
+        let mut ticket_lines = Vec::with_capacity(lines.len());
+        let mut total_amount: u64 = 0;
+        for (index, line) in lines.iter_mut().enumerate() {
+            require!(line.multiplier > 0, CustomError::InsufficientAmount);
+            if line.quick_pick {
+                line.numbers = convert_random_to_numbers(&quick_pick_seed(
+                    &lottery.key(),
+                    lottery.ticket_count,
+                    &ctx.accounts.buyer.key(),
+                    &clock,
+                    index,
+                ));
+            }
+
+            let reds = line.numbers[..6].to_vec();
+            let blues = vec![line.numbers[6]];
+            require!(
+                validate_selection(&reds, &blues),
+                CustomError::InvalidTicketNumbers
+            );
+
+            total_amount = (lottery.min_purchase_amount as u64)
+                .checked_mul(line.multiplier as u64)
+                .and_then(|amount| total_amount.checked_add(amount))
+                .ok_or(CustomError::ArithmeticError)?;
+            ticket_lines.push(TicketLine {
+                reds,
+                blues,
+                units: line.multiplier as u64,
+            });
+        }
 
-        let recent_blockhashes = ctx.accounts.recent_blockhashes.try_borrow_data()?;
-        let lottery_info = lottery.to_account_info();
-        let random_value = generate_vrf_random_number(
-            current_timestamp,
-            &recent_blockhashes,
-            &lottery_info,
-            &uuid,
+        let transfer_ctx = CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.buyer_token_account.to_account_info(),
+                to: ctx.accounts.lottery_token_account.to_account_info(),
+                authority: ctx.accounts.buyer.to_account_info(),
+            },
+        );
+        token::transfer(transfer_ctx, total_amount)?;
+
+        let ticket = &mut ctx.accounts.ticket;
+        ticket.buyer = ctx.accounts.buyer.key();
+        ticket.lines = ticket_lines;
+        enter_ticket(
+            lottery,
+            &mut ctx.accounts.round,
+            ticket,
+            total_amount,
+            clock.unix_timestamp,
         )?;
 
+        emit!(TicketLinesPurchased {
+            buyer: ticket.buyer,
+            round_id: ticket.round_id,
+            ticket_id: ticket.ticket_id,
+            lines,
+            amount: total_amount,
+        });
+
+        Ok(())
+    }
+
+    pub fn commit_draw(ctx: Context<CommitDraw>, commitment: [u8; 32]) -> Result<()> {
+        let round = &mut ctx.accounts.round;
+
+        require!(
+            ctx.accounts
+                .lottery
+                .pending_draw_time(Clock::get()?.unix_timestamp)
+                .is_none(),
+            CustomError::SalesClosed
+        );
+        require!(!round.is_drawn, CustomError::AlreadyDrawn);
+        require!(round.commitment == [0u8; 32], CustomError::AlreadyCommitted);
+        require!(commitment != [0u8; 32], CustomError::InvalidCommitment);
+
+        round.commitment = commitment;
+        round.commit_slot = Clock::get()?.slot;
+
+        emit!(DrawCommitted {
+            round_id: round.round_id,
+            commitment,
+            commit_slot: round.commit_slot,
+        });
+
+        Ok(())
+    }
+
+    pub fn lock_draw(ctx: Context<LockDraw>) -> Result<()> {
+        let clock = Clock::get()?;
+        let lottery = &ctx.accounts.lottery;
+
+        let scheduled_time = lottery
+            .current_draw_time(clock.unix_timestamp)
+            .ok_or(CustomError::InvalidDrawTime)?;
+        require!(
+            !lottery.is_locked && lottery.last_draw_time < scheduled_time,
+            CustomError::AlreadyDrawn
+        );
+
+        let round = &mut ctx.accounts.round;
+        require!(round.commitment != [0u8; 32], CustomError::DrawNotCommitted);
+
+        require!(round.target_slot == 0, CustomError::DrawAlreadyLocked);
+
+        round.target_slot = clock
+            .slot
+            .checked_add(DRAW_DELAY_SLOTS)
+            .ok_or(CustomError::ArithmeticError)?;
+
+        emit!(DrawLocked {
+            round_id: round.round_id,
+            target_slot: round.target_slot,
+        });
+
+        Ok(())
+    }
+
+    pub fn draw(ctx: Context<Draw>, secret: [u8; 32]) -> Result<()> {
+        let clock = Clock::get()?;
+        let current_timestamp = clock.unix_timestamp;
+
+        let lottery = &mut ctx.accounts.lottery;
+
+        let scheduled_time = lottery
+            .current_draw_time(current_timestamp)
+            .ok_or(CustomError::InvalidDrawTime)?;
+
+        require!(
+            !lottery.is_locked && lottery.last_draw_time < scheduled_time,
+            CustomError::AlreadyDrawn
+        );
+
+        let round = &mut ctx.accounts.round;
+        require!(round.target_slot != 0, CustomError::DrawNotLocked);
+        require!(clock.slot > round.target_slot, CustomError::RevealTooEarly);
+
+        require!(
+            hash(&secret).to_bytes() == round.commitment,
+            CustomError::InvalidReveal
+        );
+
+        let (slot, slot_hash) = find_draw_slot_hash(
+            &ctx.accounts.slot_hashes.try_borrow_data()?,
+            round.target_slot,
+        )
+        .ok_or(CustomError::DrawExpired)?;
+
+        let random_value =
+            generate_draw_random_number(&secret, &slot_hash, &lottery.key(), round.round_id);
+
         let draw_numbers = convert_random_to_numbers(&random_value);
 
         lottery.last_draw_time = current_timestamp;
         lottery.is_locked = true;
         lottery.last_draw_numbers = draw_numbers;
 
+        round.is_drawn = true;
+        round.draw_time = current_timestamp;
+        round.draw_numbers = draw_numbers;
+        round.draw_slot = slot;
+        round.slot_hash = slot_hash;
+        round.secret = secret;
+        // The jackpot is fixed at draw time; rollovers from rounds finalized
+        // later join the next drawn round.
+        round.jackpot = std::mem::take(&mut lottery.jackpot_pool);
+
+        lottery.current_round = lottery
+            .current_round
+            .checked_add(1)
+            .ok_or(CustomError::ArithmeticError)?;
+
+        let next_round = &mut ctx.accounts.next_round;
+        next_round.lottery = lottery.key();
+        next_round.round_id = lottery.current_round;
+        next_round.start_time = current_timestamp;
+        next_round.ticket_count = lottery.next_round_ticket_count;
+        next_round.total_amount = lottery.next_round_amount;
+        next_round.prize_pool = std::mem::take(&mut lottery.next_round_prize_pool);
+        // Tickets rolled forward were sold under these tiers; updates wait for them.
+        next_round.prize_tiers = lottery.prize_tiers;
+
+        lottery.jackpot_pool = std::mem::take(&mut lottery.next_round_jackpot);
+        lottery.next_round_ticket_count = 0;
+        lottery.next_round_amount = 0;
+
+        let top_up = jackpot_top_up(
+            lottery.min_jackpot,
+            lottery.jackpot_pool,
+            lottery.reserve_pool,
+        );
+        if top_up > 0 {
+            lottery.reserve_pool = lottery
+                .reserve_pool
+                .checked_sub(top_up)
+                .ok_or(CustomError::ArithmeticError)?;
+            lottery.jackpot_pool = lottery
+                .jackpot_pool
+                .checked_add(top_up)
+                .ok_or(CustomError::ArithmeticError)?;
+
+            emit!(JackpotToppedUp {
+                round_id: next_round.round_id,
+                amount: top_up,
+                jackpot_pool: lottery.jackpot_pool,
+                reserve_pool: lottery.reserve_pool,
+            });
+        }
+
         emit!(DrawResult {
+            round_id: round.round_id,
             numbers: draw_numbers,
             draw_time: lottery.last_draw_time,
         });
@@ -115,213 +458,1339 @@ pub mod lottery_contract {
         Ok(())
     }
 
-    pub fn update_prize_amount(ctx: Context<UpdatePrize>, prize_amount: u64) -> Result<()> {
-        let lottery = &mut ctx.accounts.lottery;
+    pub fn void_round(ctx: Context<Draw>) -> Result<()> {
         let clock = Clock::get()?;
-        let current_timestamp = clock.unix_timestamp;
+        let lottery = &mut ctx.accounts.lottery;
 
+        // A locked round whose slot hash aged out unrevealed can no longer be
+        // drawn, so it is voided and its tickets are refunded in full.
+        let round = &mut ctx.accounts.round;
+        require!(round.target_slot != 0, CustomError::DrawNotLocked);
         require!(
-            lottery.is_locked && current_timestamp - lottery.last_draw_time <= LOCK_DURATION,
-            CustomError::PrizeUpdateWindowClosed
+            clock.slot > round.target_slot + solana_program::slot_hashes::MAX_ENTRIES as u64
+                && find_draw_slot_hash(
+                    &ctx.accounts.slot_hashes.try_borrow_data()?,
+                    round.target_slot
+                )
+                .is_none(),
+            CustomError::RevealStillPossible
         );
-        require!(prize_amount > 0, CustomError::InvalidPrizeAmount);
+        round.is_voided = true;
 
-        lottery.last_prize_amount += prize_amount;
+        lottery.current_round = lottery
+            .current_round
+            .checked_add(1)
+            .ok_or(CustomError::ArithmeticError)?;
 
-        emit!(PrizeAmountUpdated {
-            amount: prize_amount,
-            draw_time: lottery.last_draw_time,
+        let next_round = &mut ctx.accounts.next_round;
+        next_round.lottery = lottery.key();
+        next_round.round_id = lottery.current_round;
+        next_round.start_time = clock.unix_timestamp;
+        next_round.ticket_count = lottery.next_round_ticket_count;
+        next_round.total_amount = lottery.next_round_amount;
+        next_round.prize_pool = std::mem::take(&mut lottery.next_round_prize_pool);
+        next_round.prize_tiers = lottery.prize_tiers;
+
+        // The jackpot was not drawn, so it carries over to the next round.
+        lottery.jackpot_pool = lottery
+            .jackpot_pool
+            .checked_add(std::mem::take(&mut lottery.next_round_jackpot))
+            .ok_or(CustomError::ArithmeticError)?;
+        lottery.next_round_ticket_count = 0;
+        lottery.next_round_amount = 0;
+
+        emit!(RoundVoided {
+            round_id: round.round_id,
+            ticket_count: round.ticket_count,
+            refund_amount: round.total_amount,
         });
 
         Ok(())
     }
 
-    pub fn withdraw_sol(ctx: Context<WithdrawSol>, amount: u64) -> Result<()> {
-        let rent_balance =
-            Rent::get()?.minimum_balance(ctx.accounts.lottery.to_account_info().data_len());
-        let available_balance = ctx
-            .accounts
-            .lottery
-            .to_account_info()
-            .lamports()
-            .checked_sub(rent_balance)
-            .ok_or(CustomError::InsufficientBalance)?;
-
+    pub fn update_prize_tiers(
+        ctx: Context<UpdatePrize>,
+        prize_tiers: [PrizeTier; PRIZE_TIERS],
+    ) -> Result<()> {
+        let lottery = &mut ctx.accounts.lottery;
+        // The open round keeps the tiers it opened with, so only tickets
+        // already sold for the round after it could see a change.
         require!(
-            amount <= available_balance,
-            CustomError::InsufficientBalance
+            lottery.next_round_ticket_count == 0,
+            CustomError::PrizeTiersInUse
+        );
+        require!(
+            validate_prize_tiers(
+                &prize_tiers,
+                lottery.jackpot_share_bps,
+                lottery.revenue_split.prize_bps
+            ),
+            CustomError::InvalidPrizeTiers
         );
 
-        **ctx
-            .accounts
-            .lottery
-            .to_account_info()
-            .try_borrow_mut_lamports()? -= amount;
-        **ctx.accounts.authority.try_borrow_mut_lamports()? += amount;
+        lottery.prize_tiers = prize_tiers;
+
+        emit!(PrizeTiersUpdated { prize_tiers });
 
         Ok(())
     }
 
-    pub fn transfer_token<'info>(
-        ctx: Context<'_, '_, '_, 'info, TransferToken<'info>>,
-        transfers: Vec<TransferInfo>,
-        total_amount: u64
+    pub fn update_jackpot(
+        ctx: Context<UpdatePrize>,
+        jackpot_share_bps: u16,
+        min_jackpot: u64,
     ) -> Result<()> {
-        require!(total_amount > 0, CustomError::InsufficientPrizeAmount);
+        let lottery = &mut ctx.accounts.lottery;
         require!(
-            ctx.accounts.lottery.last_prize_amount >= total_amount,
-            CustomError::InsufficientPrizeAmount
+            validate_prize_tiers(
+                &lottery.prize_tiers,
+                jackpot_share_bps,
+                lottery.revenue_split.prize_bps
+            ),
+            CustomError::InvalidPrizeTiers
         );
 
-        let auth_key = ctx.accounts.authority.key();
-        for (i, transfer) in transfers.iter().enumerate() {
-            let recipient_account = ctx
-                .remaining_accounts
-                .get(i)
-                .ok_or(CustomError::InvalidTokenMint)?;
+        lottery.jackpot_share_bps = jackpot_share_bps;
+        lottery.min_jackpot = min_jackpot;
 
-            let signer_seeds: &[&[&[u8]]] =
-                &[&[b"lottery", auth_key.as_ref(), &[ctx.bumps.lottery]]];
+        emit!(JackpotUpdated {
+            jackpot_share_bps,
+            min_jackpot,
+        });
 
-            token::transfer(
-                CpiContext::new_with_signer(
-                    ctx.accounts.token_program.to_account_info(),
-                    token::Transfer {
-                        from: ctx.accounts.lottery_token_account.to_account_info(),
-                        to: recipient_account.to_account_info(),
-                        authority: ctx.accounts.lottery.to_account_info(),
-                    },
-                    signer_seeds,
-                ),
-                transfer.amount,
-            )?;
+        Ok(())
+    }
+
+    pub fn fund_reserve(ctx: Context<FundReserve>, amount: u64) -> Result<()> {
+        require!(amount > 0, CustomError::InvalidPrizeAmount);
+
+        token::transfer(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.authority_token_account.to_account_info(),
+                    to: ctx.accounts.lottery_token_account.to_account_info(),
+                    authority: ctx.accounts.authority.to_account_info(),
+                },
+            ),
+            amount,
+        )?;
 
-            emit!(TokenDrawTransfer {
-                amount: transfer.amount,
-                recipient: transfer.recipient,
-                remaining_prize: ctx.accounts.lottery.last_prize_amount - transfer.amount,
-            });
-        }
-        
         let lottery = &mut ctx.accounts.lottery;
-        lottery.last_prize_amount = lottery
-            .last_prize_amount
-            .checked_sub(total_amount)
+        lottery.reserve_pool = lottery
+            .reserve_pool
+            .checked_add(amount)
             .ok_or(CustomError::ArithmeticError)?;
 
-        emit!(BatchTransferCompleted {
-            total_amount,
-            transfer_count: transfers.len() as u8,
-            remaining_prize: lottery.last_prize_amount,
-            timestamp: Clock::get()?.unix_timestamp,
+        emit!(ReserveFunded {
+            amount,
+            reserve_pool: lottery.reserve_pool,
         });
 
         Ok(())
     }
-}
 
-#[account]
-pub struct LotteryState {
-    pub authority: Pubkey,
-    pub token_account: Pubkey,
-    pub token_mint: Pubkey,
-    pub last_draw_time: i64,
-    pub is_locked: bool,
-    pub min_purchase_amount: u32,
-    pub last_draw_numbers: [u8; 7],
-    pub last_prize_amount: u64,
-}
+    pub fn settle_ticket(ctx: Context<SettleTicket>) -> Result<()> {
+        let round = &mut ctx.accounts.round;
+        let ticket = &mut ctx.accounts.ticket;
+
+        require!(round.is_drawn, CustomError::RoundNotDrawn);
+        require!(!round.is_finalized, CustomError::RoundAlreadyFinalized);
+        require!(!ticket.is_settled, CustomError::TicketAlreadySettled);
+
+        let mut tier_wins = [0u64; PRIZE_TIERS];
+        for line in ticket.lines.iter() {
+            let tier_lines = winning_lines(&line.reds, &line.blues, &round.draw_numbers);
+            for (tier, lines) in tier_lines.into_iter().enumerate() {
+                tier_wins[tier] = lines
+                    .checked_mul(line.units)
+                    .and_then(|wins| tier_wins[tier].checked_add(wins))
+                    .ok_or(CustomError::ArithmeticError)?;
+            }
+        }
+        for (tier, wins) in tier_wins.into_iter().enumerate() {
+            round.tier_winners[tier] = round.tier_winners[tier]
+                .checked_add(wins)
+                .ok_or(CustomError::ArithmeticError)?;
+        }
 
-#[derive(Accounts)]
-pub struct UpdatePrize<'info> {
-    #[account(
-        mut,
-        seeds = [b"lottery", lottery.authority.as_ref()],
-        bump,
-        has_one = authority
-    )]
-    pub lottery: Account<'info, LotteryState>,
-    pub authority: Signer<'info>,
-}
+        ticket.tier_wins = tier_wins;
 
-#[derive(Accounts)]
-pub struct Initialize<'info> {
-    #[account(
-        init,
-        payer = authority,
-        space = 8 + 32 + 32 + 32 + 8 + 1 + 4 + 7 + 8,
-        seeds = [b"lottery", authority.key().as_ref()],
-        bump
-    )]
-    pub lottery: Account<'info, LotteryState>,
+        ticket.is_settled = true;
+        round.settled_count = round
+            .settled_count
+            .checked_add(1)
+            .ok_or(CustomError::ArithmeticError)?;
 
-    #[account(
-        init,
-        payer = authority,
-        seeds = [b"token_account", authority.key().as_ref()],
-        bump,
-        token::mint = token_mint,
-        token::authority = lottery,
-    )]
-    pub token_account: Box<Account<'info, TokenAccount>>,
+        emit!(TicketSettled {
+            round_id: round.round_id,
+            ticket_id: ticket.ticket_id,
+            tier_wins: ticket.tier_wins,
+        });
 
-    /// CHECK: Token mint account
-    pub token_mint: AccountInfo<'info>,
+        Ok(())
+    }
 
-    #[account(mut)]
-    pub authority: Signer<'info>,
+    pub fn finalize_round(ctx: Context<FinalizeRound>) -> Result<()> {
+        let lottery = &mut ctx.accounts.lottery;
+        let round = &mut ctx.accounts.round;
 
-    pub system_program: Program<'info, System>,
-    pub token_program: Program<'info, Token>,
-    pub rent: Sysvar<'info, Rent>,
-}
+        require!(
+            round.is_drawn || round.is_voided,
+            CustomError::RoundNotDrawn
+        );
+        require!(!round.is_finalized, CustomError::RoundAlreadyFinalized);
+        require!(
+            round.is_voided || round.settled_count == round.ticket_count,
+            CustomError::RoundNotSettled
+        );
 
-#[derive(Accounts)]
-pub struct BuyTicket<'info> {
-    #[account(
-        mut,
-        seeds = [b"lottery", lottery.authority.as_ref()],
-        bump
-    )]
-    pub lottery: Account<'info, LotteryState>,
+        // Pari-mutuel tiers share a cut of round sales; the top tier also takes
+        // the round jackpot. Any pool without winners rolls into the jackpot.
+        let mut rollover: u64 = 0;
+        let mut from_sales: u64 = 0;
+        if round.is_voided {
+            // A voided round refunds every ticket in full.
+            from_sales = round.total_amount;
+            round.total_prize = round.total_amount;
+            lottery.last_prize_amount = lottery
+                .last_prize_amount
+                .checked_add(round.total_amount)
+                .ok_or(CustomError::ArithmeticError)?;
+        } else {
+            for tier in 0..PRIZE_TIERS {
+                let pool_bps = round.prize_tiers[tier].pool_bps as u64;
+                if tier > 0 && pool_bps == 0 {
+                    let fixed_prizes = round.prize_tiers[tier]
+                        .fixed_prize
+                        .checked_mul(round.tier_winners[tier])
+                        .ok_or(CustomError::ArithmeticError)?;
+                    from_sales = from_sales
+                        .checked_add(fixed_prizes)
+                        .ok_or(CustomError::ArithmeticError)?;
+                    round.total_prize = round
+                        .total_prize
+                        .checked_add(fixed_prizes)
+                        .ok_or(CustomError::ArithmeticError)?;
+                    lottery.last_prize_amount = lottery
+                        .last_prize_amount
+                        .checked_add(fixed_prizes)
+                        .ok_or(CustomError::ArithmeticError)?;
+                    continue;
+                }
+
+                let mut pool = (round.total_amount as u128 * pool_bps as u128
+                    / BPS_DENOMINATOR as u128) as u64;
+                from_sales = from_sales
+                    .checked_add(pool)
+                    .ok_or(CustomError::ArithmeticError)?;
+                if tier == 0 {
+                    pool = pool
+                        .checked_add(round.jackpot)
+                        .ok_or(CustomError::ArithmeticError)?;
+                }
+
+                if round.tier_winners[tier] == 0 {
+                    rollover = rollover
+                        .checked_add(pool)
+                        .ok_or(CustomError::ArithmeticError)?;
+                } else {
+                    round.tier_pools[tier] = pool;
+                    round.total_prize = round
+                        .total_prize
+                        .checked_add(pool)
+                        .ok_or(CustomError::ArithmeticError)?;
+                    lottery.last_prize_amount = lottery
+                        .last_prize_amount
+                        .checked_add(pool)
+                        .ok_or(CustomError::ArithmeticError)?;
+                }
+            }
+        }
 
-    #[account(
+        lottery.jackpot_pool = lottery
+            .jackpot_pool
+            .checked_add(rollover)
+            .ok_or(CustomError::ArithmeticError)?;
+
+        // Pools and fixed prizes draw on the round's prize share first, then the
+        // reserve, then house revenue. An unused prize share tops up the reserve.
+        let (from_pool, from_reserve, from_house) = fund_prize(
+            from_sales,
+            round.prize_pool,
+            lottery.reserve_pool,
+            lottery.house_revenue,
+        );
+        lottery.open_prize_pool = lottery
+            .open_prize_pool
+            .checked_sub(round.prize_pool)
+            .ok_or(CustomError::ArithmeticError)?;
+        lottery.reserve_pool = lottery
+            .reserve_pool
+            .checked_sub(from_reserve)
+            .and_then(|reserve| reserve.checked_add(round.prize_pool - from_pool))
+            .ok_or(CustomError::ArithmeticError)?;
+        lottery.house_revenue = lottery
+            .house_revenue
+            .checked_sub(from_house)
+            .ok_or(CustomError::ArithmeticError)?;
+        lottery.finalized_rounds = lottery
+            .finalized_rounds
+            .checked_add(1)
+            .ok_or(CustomError::ArithmeticError)?;
+        round.is_finalized = true;
+
+        emit!(RoundFinalized {
+            round_id: round.round_id,
+            tier_winners: round.tier_winners,
+            tier_pools: round.tier_pools,
+            jackpot_pool: lottery.jackpot_pool,
+        });
+
+        Ok(())
+    }
+
+    pub fn claim(ctx: Context<Claim>) -> Result<()> {
+        let round = &mut ctx.accounts.round;
+        let ticket = &mut ctx.accounts.ticket;
+
+        require!(round.is_finalized, CustomError::RoundNotFinalized);
+        require!(!ticket.is_paid, CustomError::TicketAlreadyClaimed);
+
+        let prize_amount = ticket_prize(round, ticket).ok_or(CustomError::ArithmeticError)?;
+
+        require!(prize_amount > 0, CustomError::NoPrize);
+        require!(
+            ctx.accounts.lottery.last_prize_amount >= prize_amount,
+            CustomError::InsufficientPrizeAmount
+        );
+
+        let auth_key = ctx.accounts.lottery.authority;
+        let lottery_id = ctx.accounts.lottery.lottery_id.to_le_bytes();
+        let signer_seeds: &[&[&[u8]]] = &[&[
+            b"lottery",
+            auth_key.as_ref(),
+            &lottery_id,
+            &[ctx.bumps.lottery],
+        ]];
+
+        token::transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                token::Transfer {
+                    from: ctx.accounts.lottery_token_account.to_account_info(),
+                    to: ctx.accounts.buyer_token_account.to_account_info(),
+                    authority: ctx.accounts.lottery.to_account_info(),
+                },
+                signer_seeds,
+            ),
+            prize_amount,
+        )?;
+
+        ticket.is_paid = true;
+        ticket.prize_amount = prize_amount;
+        round.total_paid = round
+            .total_paid
+            .checked_add(prize_amount)
+            .ok_or(CustomError::ArithmeticError)?;
+
+        let lottery = &mut ctx.accounts.lottery;
+        lottery.last_prize_amount = lottery
+            .last_prize_amount
+            .checked_sub(prize_amount)
+            .ok_or(CustomError::ArithmeticError)?;
+
+        emit!(PrizeClaimed {
+            round_id: ticket.round_id,
+            ticket_id: ticket.ticket_id,
+            buyer: ticket.buyer,
+            tier_wins: ticket.tier_wins,
+            amount: prize_amount,
+        });
+
+        Ok(())
+    }
+
+    pub fn update_schedule(ctx: Context<UpdatePrize>, schedule: DrawSchedule) -> Result<()> {
+        require!(schedule.is_valid(), CustomError::InvalidSchedule);
+
+        let lottery = &mut ctx.accounts.lottery;
+        require!(
+            lottery
+                .pending_draw_time(Clock::get()?.unix_timestamp)
+                .is_none(),
+            CustomError::SalesClosed
+        );
+
+        lottery.schedule = schedule;
+
+        emit!(ScheduleUpdated {
+            weekday_mask: schedule.weekday_mask,
+            draw_time: schedule.draw_time,
+            utc_offset: schedule.utc_offset,
+            draw_window: schedule.draw_window,
+            sales_close: schedule.sales_close,
+        });
+
+        Ok(())
+    }
+
+    pub fn set_skipped_days(ctx: Context<UpdatePrize>, skipped_days: Vec<u32>) -> Result<()> {
+        require!(
+            skipped_days.len() <= MAX_SKIPPED_DAYS,
+            CustomError::TooManySkippedDays
+        );
+
+        ctx.accounts.lottery.skipped_days = skipped_days.clone();
+
+        emit!(SkippedDaysUpdated { skipped_days });
+
+        Ok(())
+    }
+
+    pub fn update_revenue_split(
+        ctx: Context<UpdatePrize>,
+        revenue_split: RevenueSplit,
+    ) -> Result<()> {
+        let lottery = &mut ctx.accounts.lottery;
+        require!(revenue_split.is_valid(), CustomError::InvalidRevenueSplit);
+        require!(
+            revenue_split.covers_fees(lottery.fee_bps()),
+            CustomError::FeeSplitTooHigh
+        );
+        require!(
+            validate_prize_tiers(
+                &lottery.prize_tiers,
+                lottery.jackpot_share_bps,
+                revenue_split.prize_bps
+            ),
+            CustomError::InvalidPrizeTiers
+        );
+
+        lottery.revenue_split = revenue_split;
+
+        emit!(RevenueSplitUpdated {
+            prize_bps: revenue_split.prize_bps,
+            reserve_bps: revenue_split.reserve_bps,
+            house_bps: revenue_split.house_bps,
+        });
+
+        Ok(())
+    }
+
+    pub fn withdraw_revenue(ctx: Context<WithdrawRevenue>, amount: u64) -> Result<()> {
+        let lottery = &ctx.accounts.lottery;
+        require!(
+            amount > 0 && amount <= lottery.house_revenue,
+            CustomError::InsufficientRevenue
+        );
+        // Prizes of a drawn round are only known once it is finalized.
+        require!(
+            lottery.finalized_rounds == lottery.current_round,
+            CustomError::RoundNotFinalized
+        );
+
+        // The balance left behind must still cover prizes, the prize shares of
+        // open rounds, jackpots, the reserve and partner fees not swept yet.
+        let committed = [
+            lottery.last_prize_amount,
+            lottery.open_prize_pool,
+            lottery.jackpot_pool,
+            lottery.next_round_jackpot,
+            lottery.reserve_pool,
+            lottery.accrued_fees(),
+        ]
+        .iter()
+        .try_fold(0u64, |total, &amount| total.checked_add(amount))
+        .ok_or(CustomError::ArithmeticError)?;
+        let remaining_balance = ctx
+            .accounts
+            .lottery_token_account
+            .amount
+            .checked_sub(amount)
+            .ok_or(CustomError::LiabilitiesUncovered)?;
+        require!(
+            remaining_balance >= committed,
+            CustomError::LiabilitiesUncovered
+        );
+
+        let auth_key = lottery.authority;
+        let lottery_id = lottery.lottery_id.to_le_bytes();
+        let signer_seeds: &[&[&[u8]]] = &[&[
+            b"lottery",
+            auth_key.as_ref(),
+            &lottery_id,
+            &[ctx.bumps.lottery],
+        ]];
+
+        token::transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                token::Transfer {
+                    from: ctx.accounts.lottery_token_account.to_account_info(),
+                    to: ctx.accounts.authority_token_account.to_account_info(),
+                    authority: ctx.accounts.lottery.to_account_info(),
+                },
+                signer_seeds,
+            ),
+            amount,
+        )?;
+
+        let lottery = &mut ctx.accounts.lottery;
+        lottery.house_revenue = lottery
+            .house_revenue
+            .checked_sub(amount)
+            .ok_or(CustomError::ArithmeticError)?;
+
+        emit!(RevenueWithdrawn {
+            amount,
+            house_revenue: lottery.house_revenue,
+            recipient: ctx.accounts.authority_token_account.key(),
+        });
+
+        Ok(())
+    }
+
+    pub fn update_fee_splits(
+        ctx: Context<UpdatePrize>,
+        fee_splits: Vec<FeeSplitInfo>,
+    ) -> Result<()> {
+        let lottery = &mut ctx.accounts.lottery;
+        require!(
+            fee_splits.len() <= MAX_FEE_SPLITS,
+            CustomError::TooManyFeeSplits
+        );
+
+        let mut recipients = std::collections::HashSet::new();
+        let mut fee_bps: u64 = 0;
+        for split in fee_splits.iter() {
+            require!(
+                split.bps > 0 && recipients.insert(split.recipient),
+                CustomError::InvalidFeeSplit
+            );
+            fee_bps += split.bps as u64;
+        }
+        require!(
+            lottery.revenue_split.covers_fees(fee_bps),
+            CustomError::FeeSplitTooHigh
+        );
+
+        let released_fees = lottery
+            .replace_fee_splits(&fee_splits)
+            .ok_or(CustomError::ArithmeticError)?;
+
+        emit!(FeeSplitsUpdated {
+            fee_splits,
+            released_fees,
+        });
+
+        Ok(())
+    }
+
+    pub fn sweep_fees<'info>(ctx: Context<'_, '_, '_, 'info, SweepFees<'info>>) -> Result<()> {
+        let fee_splits = ctx.accounts.lottery.fee_splits.clone();
+
+        // Recipient token accounts are passed in fee split table order.
+        require!(
+            ctx.remaining_accounts.len() == fee_splits.len(),
+            CustomError::InvalidFeeRecipient
+        );
+
+        let auth_key = ctx.accounts.lottery.authority;
+        let lottery_id = ctx.accounts.lottery.lottery_id.to_le_bytes();
+        let signer_seeds: &[&[&[u8]]] = &[&[
+            b"lottery",
+            auth_key.as_ref(),
+            &lottery_id,
+            &[ctx.bumps.lottery],
+        ]];
+
+        for (split, recipient_account) in fee_splits.iter().zip(ctx.remaining_accounts.iter()) {
+            require!(
+                recipient_account.key() == split.recipient && recipient_account.owner == &token::ID,
+                CustomError::InvalidFeeRecipient
+            );
+            if split.accrued == 0 {
+                continue;
+            }
+
+            let recipient_token_account =
+                TokenAccount::try_deserialize(&mut &recipient_account.try_borrow_data()?[..])
+                    .map_err(|_| CustomError::InvalidFeeRecipient)?;
+            require!(
+                recipient_token_account.mint == ctx.accounts.lottery.token_mint,
+                CustomError::InvalidTokenMint
+            );
+
+            token::transfer(
+                CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info(),
+                    token::Transfer {
+                        from: ctx.accounts.lottery_token_account.to_account_info(),
+                        to: recipient_account.clone(),
+                        authority: ctx.accounts.lottery.to_account_info(),
+                    },
+                    signer_seeds,
+                ),
+                split.accrued,
+            )?;
+        }
+
+        let lottery = &mut ctx.accounts.lottery;
+        for split in lottery.fee_splits.iter_mut() {
+            split.accrued = 0;
+        }
+
+        emit!(FeesSwept {
+            recipients: fee_splits.iter().map(|split| split.recipient).collect(),
+            amounts: fee_splits.iter().map(|split| split.accrued).collect(),
+        });
+
+        Ok(())
+    }
+
+    pub fn withdraw_sol(ctx: Context<WithdrawSol>, amount: u64) -> Result<()> {
+        let rent_balance =
+            Rent::get()?.minimum_balance(ctx.accounts.lottery.to_account_info().data_len());
+        let available_balance = ctx
+            .accounts
+            .lottery
+            .to_account_info()
+            .lamports()
+            .checked_sub(rent_balance)
+            .ok_or(CustomError::InsufficientBalance)?;
+
+        require!(
+            amount <= available_balance,
+            CustomError::InsufficientBalance
+        );
+
+        **ctx
+            .accounts
+            .lottery
+            .to_account_info()
+            .try_borrow_mut_lamports()? -= amount;
+        **ctx.accounts.authority.try_borrow_mut_lamports()? += amount;
+
+        Ok(())
+    }
+
+    pub fn transfer_token<'info>(
+        ctx: Context<'_, '_, '_, 'info, TransferToken<'info>>,
+        transfers: Vec<TransferInfo>,
+        total_amount: u64,
+        create_accounts: bool,
+    ) -> Result<()> {
+        require!(total_amount > 0, CustomError::InsufficientPrizeAmount);
+        require!(
+            ctx.accounts.lottery.last_prize_amount >= total_amount,
+            CustomError::InsufficientPrizeAmount
+        );
+
+        // Each payout passes its winning ticket from `round` and the recipient token account,
+        // plus the recipient wallet when missing token accounts should be created.
+        let accounts_per_transfer = if create_accounts { 3 } else { 2 };
+        require!(
+            ctx.remaining_accounts.len() == transfers.len() * accounts_per_transfer,
+            CustomError::RecipientCountMismatch
+        );
+
+        let mut transfer_sum: u64 = 0;
+        let mut recipients = std::collections::HashSet::new();
+        let mut ticket_ids = std::collections::HashSet::new();
+        for transfer in transfers.iter() {
+            require!(transfer.amount > 0, CustomError::InvalidPrizeAmount);
+            require!(
+                recipients.insert(transfer.recipient),
+                CustomError::DuplicateRecipient
+            );
+            require!(
+                ticket_ids.insert(transfer.ticket_id),
+                CustomError::DuplicatePayoutTicket
+            );
+            transfer_sum = transfer_sum
+                .checked_add(transfer.amount)
+                .ok_or(CustomError::ArithmeticError)?;
+        }
+        require!(
+            transfer_sum == total_amount,
+            CustomError::TransferTotalMismatch
+        );
+
+        let lottery_key = ctx.accounts.lottery.key();
+        let auth_key = ctx.accounts.authority.key();
+        let lottery_id = ctx.accounts.lottery.lottery_id.to_le_bytes();
+        let mut remaining_prize = ctx.accounts.lottery.last_prize_amount;
+        let mut paid_amount: u64 = 0;
+        let mut paid_count: u8 = 0;
+        for (transfer, accounts) in transfers
+            .iter()
+            .zip(ctx.remaining_accounts.chunks(accounts_per_transfer))
+        {
+            let (ticket_account, recipient_account) = (&accounts[0], &accounts[1]);
+
+            require!(
+                ticket_account.owner == &crate::ID,
+                CustomError::InvalidPayoutTicket
+            );
+            let mut ticket = Ticket::try_deserialize(&mut &ticket_account.try_borrow_data()?[..])
+                .map_err(|_| CustomError::InvalidPayoutTicket)?;
+
+            // Already-paid tickets are skipped so a failed batch can be resubmitted as is.
+            if !check_payout(&lottery_key, &ctx.accounts.round, &ticket, transfer)? {
+                emit!(PayoutSkipped {
+                    ticket_id: ticket.ticket_id,
+                    recipient: transfer.recipient,
+                    amount: transfer.amount,
+                });
+                continue;
+            }
+
+            if create_accounts {
+                let recipient_wallet = &accounts[2];
+                check_recipient_ata(
+                    &recipient_wallet.key(),
+                    &recipient_account.key(),
+                    &transfer.recipient,
+                    &ctx.accounts.lottery.token_mint,
+                )?;
+
+                associated_token::create_idempotent(CpiContext::new(
+                    ctx.accounts.associated_token_program.to_account_info(),
+                    associated_token::Create {
+                        payer: ctx.accounts.authority.to_account_info(),
+                        associated_token: recipient_account.clone(),
+                        authority: recipient_wallet.clone(),
+                        mint: ctx.accounts.mint.to_account_info(),
+                        system_program: ctx.accounts.system_program.to_account_info(),
+                        token_program: ctx.accounts.token_program.to_account_info(),
+                    },
+                ))?;
+            }
+
+            require!(
+                recipient_account.owner == &token::ID,
+                CustomError::InvalidRecipientAccount
+            );
+            let recipient_token_account =
+                TokenAccount::try_deserialize(&mut &recipient_account.try_borrow_data()?[..])
+                    .map_err(|_| CustomError::InvalidRecipientAccount)?;
+            require!(
+                recipient_token_account.mint == ctx.accounts.lottery.token_mint,
+                CustomError::InvalidTokenMint
+            );
+            require!(
+                recipient_token_account.owner == transfer.recipient,
+                CustomError::RecipientOwnerMismatch
+            );
+
+            let signer_seeds: &[&[&[u8]]] = &[&[
+                b"lottery",
+                auth_key.as_ref(),
+                &lottery_id,
+                &[ctx.bumps.lottery],
+            ]];
+
+            token::transfer(
+                CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info(),
+                    token::Transfer {
+                        from: ctx.accounts.lottery_token_account.to_account_info(),
+                        to: recipient_account.to_account_info(),
+                        authority: ctx.accounts.lottery.to_account_info(),
+                    },
+                    signer_seeds,
+                ),
+                transfer.amount,
+            )?;
+
+            ticket.is_paid = true;
+            ticket.prize_amount = transfer.amount;
+            ticket.try_serialize(&mut &mut ticket_account.try_borrow_mut_data()?[..])?;
+
+            paid_amount += transfer.amount;
+            paid_count += 1;
+            remaining_prize -= transfer.amount;
+            emit!(TokenDrawTransfer {
+                amount: transfer.amount,
+                recipient: recipient_token_account.owner,
+                remaining_prize,
+            });
+        }
+
+        let round = &mut ctx.accounts.round;
+        round.total_paid = round
+            .total_paid
+            .checked_add(paid_amount)
+            .ok_or(CustomError::ArithmeticError)?;
+
+        let lottery = &mut ctx.accounts.lottery;
+        lottery.last_prize_amount = lottery
+            .last_prize_amount
+            .checked_sub(paid_amount)
+            .ok_or(CustomError::ArithmeticError)?;
+
+        emit!(BatchTransferCompleted {
+            total_amount: paid_amount,
+            transfer_count: paid_count,
+            remaining_prize: lottery.last_prize_amount,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+}
+
+#[account]
+#[derive(Default)]
+pub struct LotteryState {
+    pub authority: Pubkey,
+    pub lottery_id: u64,
+    pub token_account: Pubkey,
+    pub token_mint: Pubkey,
+    pub last_draw_time: i64,
+    pub is_locked: bool,
+    pub min_purchase_amount: u32,
+    pub last_draw_numbers: [u8; 7],
+    pub last_prize_amount: u64,
+    pub current_round: u64,
+    pub ticket_count: u64,
+    pub prize_tiers: [PrizeTier; PRIZE_TIERS],
+    pub schedule: DrawSchedule,
+    pub skipped_days: Vec<u32>,
+    pub next_round_ticket_count: u64,
+    pub next_round_amount: u64,
+    pub jackpot_pool: u64,
+    pub finalized_rounds: u64,
+    pub next_round_jackpot: u64,
+    pub jackpot_share_bps: u16,
+    pub min_jackpot: u64,
+    pub reserve_pool: u64,
+    pub revenue_split: RevenueSplit,
+    pub house_revenue: u64,
+    pub fee_splits: Vec<FeeSplit>,
+    pub open_prize_pool: u64,
+    pub next_round_prize_pool: u64,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default)]
+pub struct RevenueSplit {
+    pub prize_bps: u16,
+    pub reserve_bps: u16,
+    pub house_bps: u16,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default)]
+pub struct FeeSplit {
+    pub recipient: Pubkey,
+    pub bps: u16,
+    pub accrued: u64,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default)]
+pub struct PrizeTier {
+    pub fixed_prize: u64,
+    pub pool_bps: u16,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default)]
+pub struct DrawSchedule {
+    pub weekday_mask: u8,
+    pub draw_time: u32,
+    pub utc_offset: i32,
+    pub draw_window: u32,
+    pub sales_close: u32,
+}
+
+#[account]
+#[derive(Default)]
+pub struct Round {
+    pub lottery: Pubkey,
+    pub round_id: u64,
+    pub start_time: i64,
+    pub ticket_count: u64,
+    pub total_amount: u64,
+    pub is_drawn: bool,
+    pub draw_time: i64,
+    pub draw_numbers: [u8; 7],
+    pub prize_tiers: [PrizeTier; PRIZE_TIERS],
+    pub commitment: [u8; 32],
+    pub commit_slot: u64,
+    pub settled_count: u64,
+    pub tier_winners: [u64; PRIZE_TIERS],
+    pub tier_pools: [u64; PRIZE_TIERS],
+    pub is_finalized: bool,
+    pub draw_slot: u64,
+    pub slot_hash: [u8; 32],
+    pub secret: [u8; 32],
+    pub total_prize: u64,
+    pub total_paid: u64,
+    pub jackpot: u64,
+    pub target_slot: u64,
+    pub is_voided: bool,
+    pub prize_pool: u64,
+}
+
+#[account]
+#[derive(Default)]
+pub struct Ticket {
+    pub lottery: Pubkey,
+    pub round_id: u64,
+    pub ticket_id: u64,
+    pub buyer: Pubkey,
+    pub lines: Vec<TicketLine>,
+    pub amount: u64,
+    pub purchase_time: i64,
+    pub is_settled: bool,
+    pub tier_wins: [u64; PRIZE_TIERS],
+    pub is_paid: bool,
+    pub prize_amount: u64,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Default)]
+pub struct TicketLine {
+    pub reds: Vec<u8>,
+    pub blues: Vec<u8>,
+    pub units: u64,
+}
+
+#[derive(Accounts)]
+pub struct UpdatePrize<'info> {
+    #[account(
+        mut,
+        seeds = [b"lottery", lottery.authority.as_ref(), &lottery.lottery_id.to_le_bytes()],
+        bump,
+        has_one = authority
+    )]
+    pub lottery: Account<'info, LotteryState>,
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct FundReserve<'info> {
+    #[account(
+        mut,
+        seeds = [b"lottery", lottery.authority.as_ref(), &lottery.lottery_id.to_le_bytes()],
+        bump,
+        has_one = authority
+    )]
+    pub lottery: Account<'info, LotteryState>,
+
+    #[account(
+        mut,
+        seeds = [b"token_account", lottery.authority.as_ref(), &lottery.lottery_id.to_le_bytes()],
+        bump,
+        token::mint = lottery.token_mint,
+        token::authority = lottery
+    )]
+    pub lottery_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = authority_token_account.mint == lottery.token_mint @ CustomError::InvalidTokenMint
+    )]
+    pub authority_token_account: Account<'info, TokenAccount>,
+
+    pub authority: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+#[instruction(lottery_id: u64)]
+pub struct Initialize<'info> {
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + 32 + 8 + 32 + 32 + 8 + 1 + 4 + 7 + 8 + 8 + 8 + 10 * PRIZE_TIERS
+            + 17 + 4 + 4 * MAX_SKIPPED_DAYS + 8 + 8 + 8 + 8 + 8 + 2 + 8 + 8 + 6 + 8
+            + 4 + MAX_FEE_SPLITS * (32 + 2 + 8) + 8 + 8,
+        seeds = [b"lottery", authority.key().as_ref(), &lottery_id.to_le_bytes()],
+        bump
+    )]
+    pub lottery: Account<'info, LotteryState>,
+
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + 32 + 8 + 8 + 8 + 8 + 1 + 8 + 7 + 10 * PRIZE_TIERS + 32 + 8
+            + 8 + 8 * PRIZE_TIERS + 8 * PRIZE_TIERS + 1 + 8 + 32 + 32 + 8 + 8 + 8 + 8 + 1 + 8,
+        seeds = [b"round", lottery.key().as_ref(), &0u64.to_le_bytes()],
+        bump
+    )]
+    pub round: Account<'info, Round>,
+
+    #[account(
+        init,
+        payer = authority,
+        seeds = [b"token_account", authority.key().as_ref(), &lottery_id.to_le_bytes()],
+        bump,
+        token::mint = token_mint,
+        token::authority = lottery,
+    )]
+    pub token_account: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: Token mint account
+    pub token_mint: AccountInfo<'info>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct BuyTicket<'info> {
+    #[account(
+        mut,
+        seeds = [b"lottery", lottery.authority.as_ref(), &lottery.lottery_id.to_le_bytes()],
+        bump
+    )]
+    pub lottery: Account<'info, LotteryState>,
+
+    #[account(
+        mut,
+        seeds = [b"token_account", lottery.authority.as_ref(), &lottery.lottery_id.to_le_bytes()],
+        bump,
+        token::mint = lottery.token_mint,
+        token::authority = lottery
+    )]
+    pub lottery_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"round", lottery.key().as_ref(), &lottery.current_round.to_le_bytes()],
+        bump,
+        has_one = lottery
+    )]
+    pub round: Account<'info, Round>,
+
+    #[account(
+        init,
+        payer = buyer,
+        space = 8 + 32 + 8 + 8 + 32 + 4 + (4 + 6 + 4 + 1 + 8) + 8 + 8 + 1 + 8 * PRIZE_TIERS
+            + 1 + 8,
+        seeds = [b"ticket", lottery.key().as_ref(), &lottery.ticket_count.to_le_bytes()],
+        bump
+    )]
+    pub ticket: Account<'info, Ticket>,
+
+    #[account(mut)]
+    pub buyer_token_account: Account<'info, TokenAccount>,
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+#[instruction(reds: Vec<u8>, blues: Vec<u8>)]
+pub struct BuyCompoundTicket<'info> {
+    #[account(
+        mut,
+        seeds = [b"lottery", lottery.authority.as_ref(), &lottery.lottery_id.to_le_bytes()],
+        bump
+    )]
+    pub lottery: Account<'info, LotteryState>,
+
+    #[account(
+        mut,
+        seeds = [b"token_account", lottery.authority.as_ref(), &lottery.lottery_id.to_le_bytes()],
+        bump,
+        token::mint = lottery.token_mint,
+        token::authority = lottery
+    )]
+    pub lottery_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"round", lottery.key().as_ref(), &lottery.current_round.to_le_bytes()],
+        bump,
+        has_one = lottery
+    )]
+    pub round: Account<'info, Round>,
+
+    #[account(
+        init,
+        payer = buyer,
+        space = 8 + 32 + 8 + 8 + 32 + 4 + (4 + reds.len() + 4 + blues.len() + 8) + 8 + 8 + 1
+            + 8 * PRIZE_TIERS + 1 + 8,
+        seeds = [b"ticket", lottery.key().as_ref(), &lottery.ticket_count.to_le_bytes()],
+        bump
+    )]
+    pub ticket: Account<'info, Ticket>,
+
+    #[account(mut)]
+    pub buyer_token_account: Account<'info, TokenAccount>,
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+#[instruction(lines: Vec<LineInfo>)]
+pub struct BuyTickets<'info> {
+    #[account(
         mut,
-        seeds = [b"token_account", lottery.authority.as_ref()],
+        seeds = [b"lottery", lottery.authority.as_ref(), &lottery.lottery_id.to_le_bytes()],
+        bump
+    )]
+    pub lottery: Account<'info, LotteryState>,
+
+    #[account(
+        mut,
+        seeds = [b"token_account", lottery.authority.as_ref(), &lottery.lottery_id.to_le_bytes()],
         bump,
         token::mint = lottery.token_mint,
         token::authority = lottery
     )]
     pub lottery_token_account: Account<'info, TokenAccount>,
 
+    #[account(
+        mut,
+        seeds = [b"round", lottery.key().as_ref(), &lottery.current_round.to_le_bytes()],
+        bump,
+        has_one = lottery
+    )]
+    pub round: Account<'info, Round>,
+
+    #[account(
+        init,
+        payer = buyer,
+        space = 8 + 32 + 8 + 8 + 32 + 4 + lines.len() * (4 + 6 + 4 + 1 + 8) + 8 + 8 + 1
+            + 8 * PRIZE_TIERS + 1 + 8,
+        seeds = [b"ticket", lottery.key().as_ref(), &lottery.ticket_count.to_le_bytes()],
+        bump
+    )]
+    pub ticket: Account<'info, Ticket>,
+
     #[account(mut)]
     pub buyer_token_account: Account<'info, TokenAccount>,
+    #[account(mut)]
     pub buyer: Signer<'info>,
+    pub system_program: Program<'info, System>,
     pub token_program: Program<'info, Token>,
 }
 
+#[derive(Accounts)]
+pub struct CommitDraw<'info> {
+    #[account(
+        seeds = [b"lottery", lottery.authority.as_ref(), &lottery.lottery_id.to_le_bytes()],
+        bump,
+        has_one = authority
+    )]
+    pub lottery: Account<'info, LotteryState>,
+
+    #[account(
+        mut,
+        seeds = [b"round", lottery.key().as_ref(), &lottery.current_round.to_le_bytes()],
+        bump,
+        has_one = lottery
+    )]
+    pub round: Account<'info, Round>,
+
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct LockDraw<'info> {
+    #[account(
+        seeds = [b"lottery", lottery.authority.as_ref(), &lottery.lottery_id.to_le_bytes()],
+        bump
+    )]
+    pub lottery: Account<'info, LotteryState>,
+
+    #[account(
+        mut,
+        seeds = [b"round", lottery.key().as_ref(), &lottery.current_round.to_le_bytes()],
+        bump,
+        has_one = lottery
+    )]
+    pub round: Account<'info, Round>,
+}
+
 #[derive(Accounts)]
 pub struct Draw<'info> {
     #[account(
         mut,
-        seeds = [b"lottery", lottery.authority.as_ref()],
+        seeds = [b"lottery", lottery.authority.as_ref(), &lottery.lottery_id.to_le_bytes()],
+        bump
+    )]
+    pub lottery: Account<'info, LotteryState>,
+
+    #[account(
+        mut,
+        seeds = [b"round", lottery.key().as_ref(), &lottery.current_round.to_le_bytes()],
+        bump,
+        has_one = lottery
+    )]
+    pub round: Account<'info, Round>,
+
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + 32 + 8 + 8 + 8 + 8 + 1 + 8 + 7 + 10 * PRIZE_TIERS + 32 + 8
+            + 8 + 8 * PRIZE_TIERS + 8 * PRIZE_TIERS + 1 + 8 + 32 + 32 + 8 + 8 + 8 + 8 + 1 + 8,
+        seeds = [b"round", lottery.key().as_ref(), &(lottery.current_round + 1).to_le_bytes()],
+        bump
+    )]
+    pub next_round: Account<'info, Round>,
+
+    /// CHECK: SlotHashes sysvar holding the locked target slot
+    #[account(address = solana_program::sysvar::slot_hashes::ID)]
+    pub slot_hashes: AccountInfo<'info>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SettleTicket<'info> {
+    #[account(
+        seeds = [b"lottery", lottery.authority.as_ref(), &lottery.lottery_id.to_le_bytes()],
+        bump
+    )]
+    pub lottery: Account<'info, LotteryState>,
+
+    #[account(
+        mut,
+        seeds = [b"round", lottery.key().as_ref(), &ticket.round_id.to_le_bytes()],
+        bump,
+        has_one = lottery
+    )]
+    pub round: Account<'info, Round>,
+
+    #[account(
+        mut,
+        seeds = [b"ticket", lottery.key().as_ref(), &ticket.ticket_id.to_le_bytes()],
+        bump,
+        has_one = lottery
+    )]
+    pub ticket: Account<'info, Ticket>,
+}
+
+#[derive(Accounts)]
+pub struct FinalizeRound<'info> {
+    #[account(
+        mut,
+        seeds = [b"lottery", lottery.authority.as_ref(), &lottery.lottery_id.to_le_bytes()],
+        bump
+    )]
+    pub lottery: Account<'info, LotteryState>,
+
+    #[account(
+        mut,
+        seeds = [b"round", lottery.key().as_ref(), &lottery.finalized_rounds.to_le_bytes()],
+        bump,
+        has_one = lottery
+    )]
+    pub round: Account<'info, Round>,
+}
+
+#[derive(Accounts)]
+pub struct Claim<'info> {
+    #[account(
+        mut,
+        seeds = [b"lottery", lottery.authority.as_ref(), &lottery.lottery_id.to_le_bytes()],
+        bump
+    )]
+    pub lottery: Account<'info, LotteryState>,
+
+    #[account(
+        mut,
+        seeds = [b"round", lottery.key().as_ref(), &ticket.round_id.to_le_bytes()],
+        bump,
+        has_one = lottery
+    )]
+    pub round: Account<'info, Round>,
+
+    #[account(
+        mut,
+        seeds = [b"ticket", lottery.key().as_ref(), &ticket.ticket_id.to_le_bytes()],
+        bump,
+        has_one = lottery,
+        constraint = ticket.buyer == buyer.key() @ CustomError::NotTicketOwner
+    )]
+    pub ticket: Account<'info, Ticket>,
+
+    #[account(
+        mut,
+        seeds = [b"token_account", lottery.authority.as_ref(), &lottery.lottery_id.to_le_bytes()],
+        bump,
+        token::mint = lottery.token_mint,
+        token::authority = lottery
+    )]
+    pub lottery_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = buyer_token_account.mint == lottery.token_mint @ CustomError::InvalidTokenMint,
+        constraint = buyer_token_account.owner == buyer.key() @ CustomError::NotTicketOwner
+    )]
+    pub buyer_token_account: Account<'info, TokenAccount>,
+
+    pub buyer: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawRevenue<'info> {
+    #[account(
+        mut,
+        seeds = [b"lottery", lottery.authority.as_ref(), &lottery.lottery_id.to_le_bytes()],
         bump,
         has_one = authority
     )]
     pub lottery: Account<'info, LotteryState>,
 
-    /// CHECK: Recent blockhashes account for VRF
-    #[account(address = solana_program::sysvar::recent_blockhashes::ID)]
-    pub recent_blockhashes: AccountInfo<'info>,
+    #[account(
+        mut,
+        seeds = [b"token_account", lottery.authority.as_ref(), &lottery.lottery_id.to_le_bytes()],
+        bump,
+        token::mint = lottery.token_mint,
+        token::authority = lottery
+    )]
+    pub lottery_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = authority_token_account.mint == lottery.token_mint @ CustomError::InvalidTokenMint
+    )]
+    pub authority_token_account: Account<'info, TokenAccount>,
 
     pub authority: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct SweepFees<'info> {
+    #[account(
+        mut,
+        seeds = [b"lottery", lottery.authority.as_ref(), &lottery.lottery_id.to_le_bytes()],
+        bump
+    )]
+    pub lottery: Account<'info, LotteryState>,
+
+    #[account(
+        mut,
+        seeds = [b"token_account", lottery.authority.as_ref(), &lottery.lottery_id.to_le_bytes()],
+        bump,
+        token::mint = lottery.token_mint,
+        token::authority = lottery
+    )]
+    pub lottery_token_account: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
 }
 
 #[derive(Accounts)]
 pub struct WithdrawSol<'info> {
     #[account(
         mut,
-        seeds = [b"lottery", lottery.authority.as_ref()],
+        seeds = [b"lottery", lottery.authority.as_ref(), &lottery.lottery_id.to_le_bytes()],
         bump,
         has_one = authority
     )]
@@ -335,7 +1804,7 @@ pub struct WithdrawSol<'info> {
 pub struct TransferToken<'info> {
     #[account(
         mut,
-        seeds = [b"lottery", authority.key().as_ref()],
+        seeds = [b"lottery", authority.key().as_ref(), &lottery.lottery_id.to_le_bytes()],
         bump,
         has_one = authority,
     )]
@@ -343,7 +1812,15 @@ pub struct TransferToken<'info> {
 
     #[account(
         mut,
-        seeds = [b"token_account", authority.key().as_ref()],
+        seeds = [b"round", lottery.key().as_ref(), &round.round_id.to_le_bytes()],
+        bump,
+        has_one = lottery
+    )]
+    pub round: Account<'info, Round>,
+
+    #[account(
+        mut,
+        seeds = [b"token_account", authority.key().as_ref(), &lottery.lottery_id.to_le_bytes()],
         bump,
         token::mint = mint.key(),
         token::authority = lottery,
@@ -353,33 +1830,151 @@ pub struct TransferToken<'info> {
     /// CHECK: Token mint account, verified in the token_account constraint
     pub mint: AccountInfo<'info>,
 
+    #[account(mut)]
     pub authority: Signer<'info>,
     pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct LineInfo {
+    pub numbers: [u8; 7],
+    pub multiplier: u32,
+    pub quick_pick: bool,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct TransferInfo {
+    pub recipient: Pubkey,
+    pub ticket_id: u64,
+    pub amount: u64,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct FeeSplitInfo {
+    pub recipient: Pubkey,
+    pub bps: u16,
+}
+
+#[event]
+pub struct TicketPurchased {
+    pub buyer: Pubkey,
+    pub round_id: u64,
+    pub ticket_id: u64,
+    pub numbers: [u8; 7],
+    pub amount: u64,
+    pub quick_pick: bool,
+}
+
+#[event]
+pub struct CompoundTicketPurchased {
+    pub buyer: Pubkey,
+    pub round_id: u64,
+    pub ticket_id: u64,
+    pub reds: Vec<u8>,
+    pub blues: Vec<u8>,
+    pub lines: u64,
+    pub amount: u64,
+}
+
+#[event]
+pub struct TicketLinesPurchased {
+    pub buyer: Pubkey,
+    pub round_id: u64,
+    pub ticket_id: u64,
+    pub lines: Vec<LineInfo>,
+    pub amount: u64,
+}
+
+#[event]
+pub struct DrawCommitted {
+    pub round_id: u64,
+    pub commitment: [u8; 32],
+    pub commit_slot: u64,
+}
+
+#[event]
+pub struct DrawLocked {
+    pub round_id: u64,
+    pub target_slot: u64,
+}
+
+#[event]
+pub struct DrawResult {
+    pub round_id: u64,
+    pub numbers: [u8; 7],
+    pub draw_time: i64,
+}
+
+#[event]
+pub struct ScheduleUpdated {
+    pub weekday_mask: u8,
+    pub draw_time: u32,
+    pub utc_offset: i32,
+    pub draw_window: u32,
+    pub sales_close: u32,
+}
+
+#[event]
+pub struct SkippedDaysUpdated {
+    pub skipped_days: Vec<u32>,
+}
+
+#[event]
+pub struct PrizeTiersUpdated {
+    pub prize_tiers: [PrizeTier; PRIZE_TIERS],
+}
+
+#[event]
+pub struct JackpotUpdated {
+    pub jackpot_share_bps: u16,
+    pub min_jackpot: u64,
+}
+
+#[event]
+pub struct ReserveFunded {
+    pub amount: u64,
+    pub reserve_pool: u64,
 }
 
-#[derive(AnchorSerialize, AnchorDeserialize)]
-pub struct TransferInfo {
-    pub recipient: Pubkey,
+#[event]
+pub struct JackpotToppedUp {
+    pub round_id: u64,
     pub amount: u64,
+    pub jackpot_pool: u64,
+    pub reserve_pool: u64,
 }
 
 #[event]
-pub struct TicketPurchased {
-    pub buyer: Pubkey,
-    pub numbers: [u8; 7],
-    pub amount: u64,
+pub struct TicketSettled {
+    pub round_id: u64,
+    pub ticket_id: u64,
+    pub tier_wins: [u64; PRIZE_TIERS],
 }
 
 #[event]
-pub struct PrizeAmountUpdated {
-    pub amount: u64,
-    pub draw_time: i64,
+pub struct RoundVoided {
+    pub round_id: u64,
+    pub ticket_count: u64,
+    pub refund_amount: u64,
 }
 
 #[event]
-pub struct DrawResult {
-    pub numbers: [u8; 7],
-    pub draw_time: i64,
+pub struct RoundFinalized {
+    pub round_id: u64,
+    pub tier_winners: [u64; PRIZE_TIERS],
+    pub tier_pools: [u64; PRIZE_TIERS],
+    pub jackpot_pool: u64,
+}
+
+#[event]
+pub struct PrizeClaimed {
+    pub round_id: u64,
+    pub ticket_id: u64,
+    pub buyer: Pubkey,
+    pub tier_wins: [u64; PRIZE_TIERS],
+    pub amount: u64,
 }
 
 #[event]
@@ -389,6 +1984,13 @@ pub struct TokenDrawTransfer {
     pub remaining_prize: u64,
 }
 
+#[event]
+pub struct PayoutSkipped {
+    pub ticket_id: u64,
+    pub recipient: Pubkey,
+    pub amount: u64,
+}
+
 #[event]
 pub struct BatchTransferCompleted {
     pub total_amount: u64,
@@ -397,15 +1999,43 @@ pub struct BatchTransferCompleted {
     pub timestamp: i64,
 }
 
+#[event]
+pub struct RevenueSplitUpdated {
+    pub prize_bps: u16,
+    pub reserve_bps: u16,
+    pub house_bps: u16,
+}
+
+#[event]
+pub struct RevenueWithdrawn {
+    pub amount: u64,
+    pub house_revenue: u64,
+    pub recipient: Pubkey,
+}
+
+#[event]
+pub struct FeeSplitsUpdated {
+    pub fee_splits: Vec<FeeSplitInfo>,
+    pub released_fees: u64,
+}
+
+#[event]
+pub struct FeesSwept {
+    pub recipients: Vec<Pubkey>,
+    pub amounts: Vec<u64>,
+}
+
 #[error_code]
 pub enum CustomError {
     #[msg("Lottery is currently locked")]
     LotteryLocked,
     #[msg("Insufficient token amount for ticket purchase")]
     InsufficientAmount,
-    #[msg("Invalid ticket numbers: red balls (1-33), blue ball (1-16)")]
+    #[msg(
+        "Invalid ticket numbers: 6-20 distinct red balls (1-33), 1-16 distinct blue balls (1-16)"
+    )]
     InvalidTicketNumbers,
-    #[msg("Invalid draw time - must be between UTC 00:00:00 and 00:10:00")]
+    #[msg("Invalid draw time - outside the scheduled draw window")]
     InvalidDrawTime,
     #[msg("Transfer window closed")]
     TransferWindowClosed,
@@ -415,7 +2045,7 @@ pub enum CustomError {
     InvalidTokenMint,
     #[msg("Draw cannot be performed yet")]
     DrawTooEarly,
-    #[msg("Already drawn today")]
+    #[msg("Already drawn for this scheduled draw")]
     AlreadyDrawn,
     #[msg("Prize update window is closed (10 minutes after draw)")]
     PrizeUpdateWindowClosed,
@@ -427,58 +2057,1222 @@ pub enum CustomError {
     InvalidPrizeAmount,
     #[msg("Invalid transfer count, must be between 1 and 10")]
     InvalidTransferCount,
+    #[msg("Round has not been drawn yet")]
+    RoundNotDrawn,
+    #[msg("Ticket prize has already been claimed")]
+    TicketAlreadyClaimed,
+    #[msg("Ticket did not win a prize")]
+    NoPrize,
+    #[msg("Signer does not own this ticket")]
+    NotTicketOwner,
+    #[msg("Ticket sales are closed for the draw")]
+    SalesClosed,
+    #[msg("Draw commitment already submitted for this round")]
+    AlreadyCommitted,
+    #[msg("Draw commitment must not be empty")]
+    InvalidCommitment,
+    #[msg("No draw commitment submitted for this round")]
+    DrawNotCommitted,
+    #[msg("Revealed secret does not match the commitment")]
+    InvalidReveal,
+    #[msg("Draw target slot has not been reached yet")]
+    RevealTooEarly,
+    #[msg("Invalid draw schedule")]
+    InvalidSchedule,
+    #[msg("Too many skipped draw days")]
+    TooManySkippedDays,
+    #[msg("Invalid prize tiers: pool tiers first, no fixed jackpot, pools within the prize share")]
+    InvalidPrizeTiers,
+    #[msg("Ticket has already been settled")]
+    TicketAlreadySettled,
+    #[msg("Round has already been finalized")]
+    RoundAlreadyFinalized,
+    #[msg("All tickets must be settled before finalizing the round")]
+    RoundNotSettled,
+    #[msg("Round prizes have not been finalized yet")]
+    RoundNotFinalized,
+    #[msg("Draw target slot has not been locked")]
+    DrawNotLocked,
+    #[msg("Draw target slot is already locked")]
+    DrawAlreadyLocked,
+    #[msg("Draw target slot hash is no longer in the SlotHashes sysvar")]
+    DrawExpired,
+    #[msg("Purchase amount must be a whole number of ticket units")]
+    InvalidPurchaseAmount,
+    #[msg("A ticket must have between 1 and 32 lines")]
+    InvalidLineCount,
+    #[msg(
+        "Each transfer needs its ticket and token account, plus the wallet when creating accounts"
+    )]
+    RecipientCountMismatch,
+    #[msg("Transfer amounts do not add up to the total amount")]
+    TransferTotalMismatch,
+    #[msg("Recipient appears more than once in the batch")]
+    DuplicateRecipient,
+    #[msg("Recipient account is not a token account")]
+    InvalidRecipientAccount,
+    #[msg("Recipient token account is not owned by the listed recipient")]
+    RecipientOwnerMismatch,
+    #[msg("Payout ticket does not belong to this lottery or batch entry")]
+    InvalidPayoutTicket,
+    #[msg("Ticket has not been settled yet")]
+    TicketNotSettled,
+    #[msg("Payout amount does not match the settled ticket prize")]
+    PayoutAmountMismatch,
+    #[msg("Ticket appears more than once in the batch")]
+    DuplicatePayoutTicket,
+    #[msg("Recipient account is not the recipient's associated token account")]
+    InvalidAssociatedTokenAccount,
+    #[msg("Revenue split must add up to 100%")]
+    InvalidRevenueSplit,
+    #[msg("Withdrawal exceeds the accrued house revenue")]
+    InsufficientRevenue,
+    #[msg("Withdrawal would leave prize liabilities uncovered")]
+    LiabilitiesUncovered,
+    #[msg("At most 4 fee splits are allowed")]
+    TooManyFeeSplits,
+    #[msg("Fee splits need a non-zero share and distinct recipients")]
+    InvalidFeeSplit,
+    #[msg("Fee splits plus the prize and reserve shares exceed 100%")]
+    FeeSplitTooHigh,
+    #[msg("Fee recipient account does not match the fee split table")]
+    InvalidFeeRecipient,
+    #[msg("Prize tiers cannot change while the next round already has tickets")]
+    PrizeTiersInUse,
+    #[msg("Draw target slot hash can still be revealed")]
+    RevealStillPossible,
+}
+
+impl RevenueSplit {
+    pub fn is_valid(&self) -> bool {
+        self.prize_bps as u64 + self.reserve_bps as u64 + self.house_bps as u64 == BPS_DENOMINATOR
+    }
+
+    pub fn shares(&self, amount: u64) -> (u64, u64) {
+        let share = |bps: u16| (amount as u128 * bps as u128 / BPS_DENOMINATOR as u128) as u64;
+        (share(self.reserve_bps), share(self.house_bps))
+    }
+
+    pub fn covers_fees(&self, fee_bps: u64) -> bool {
+        self.prize_bps as u64 + self.reserve_bps as u64 + fee_bps <= BPS_DENOMINATOR
+    }
+}
+
+impl LotteryState {
+    pub fn fee_bps(&self) -> u64 {
+        self.fee_splits.iter().map(|split| split.bps as u64).sum()
+    }
+
+    // Installs a new fee table. Fees a dropped recipient never swept go back to
+    // house revenue, so a dead recipient account cannot pin the table.
+    pub fn replace_fee_splits(&mut self, fee_splits: &[FeeSplitInfo]) -> Option<u64> {
+        let (splits, released) = carry_over_fees(&self.fee_splits, fee_splits)?;
+        self.house_revenue = self.house_revenue.checked_add(released)?;
+        self.fee_splits = splits;
+        Some(released)
+    }
+
+    pub fn accrued_fees(&self) -> u64 {
+        self.fee_splits.iter().map(|split| split.accrued).sum()
+    }
+
+    pub fn open_sales(&mut self, now: i64) -> Result<()> {
+        if self.is_locked {
+            require!(
+                now - self.last_draw_time > LOCK_DURATION,
+                CustomError::LotteryLocked
+            );
+            self.is_locked = false;
+        }
+        Ok(())
+    }
+
+    pub fn scheduled_draw_on(&self, local_day: i64) -> Option<i64> {
+        // Day 0 (1970-01-01) was a Thursday; bit 0 of the mask is Sunday.
+        let weekday = (local_day + 4).rem_euclid(7);
+        if self.schedule.weekday_mask & (1 << weekday) == 0
+            || self.skipped_days.iter().any(|&day| day as i64 == local_day)
+        {
+            return None;
+        }
+        Some(
+            local_day * SECONDS_PER_DAY + self.schedule.draw_time as i64
+                - self.schedule.utc_offset as i64,
+        )
+    }
+
+    pub fn current_draw_time(&self, timestamp: i64) -> Option<i64> {
+        self.find_draw_time(timestamp, 0)
+    }
+
+    pub fn pending_draw_time(&self, timestamp: i64) -> Option<i64> {
+        self.find_draw_time(timestamp, self.schedule.sales_close as i64)
+            .filter(|&draw_time| self.last_draw_time < draw_time)
+    }
+
+    // Once sales close for the pending draw, tickets join the round after it.
+    pub fn ticket_round(&self, timestamp: i64) -> u64 {
+        self.current_round + self.pending_draw_time(timestamp).is_some() as u64
+    }
+
+    fn find_draw_time(&self, timestamp: i64, lead_time: i64) -> Option<i64> {
+        let local_day = (timestamp + self.schedule.utc_offset as i64).div_euclid(SECONDS_PER_DAY);
+        (local_day - 1..=local_day + 1)
+            .filter_map(|day| self.scheduled_draw_on(day))
+            .find(|&draw_time| {
+                (draw_time - lead_time..=draw_time + self.schedule.draw_window as i64)
+                    .contains(&timestamp)
+            })
+    }
+}
+
+impl DrawSchedule {
+    pub fn is_valid(&self) -> bool {
+        self.weekday_mask & 0x7f != 0
+            && self.weekday_mask & 0x80 == 0
+            && (self.draw_time as i64) < SECONDS_PER_DAY
+            && self.draw_window > 0
+            && (self.draw_window as i64) < SECONDS_PER_DAY
+            && (self.sales_close as i64) < SECONDS_PER_DAY
+            && (self.utc_offset as i64).abs() < SECONDS_PER_DAY
+    }
+}
+
+impl FeeSplit {
+    pub fn fee(&self, amount: u64) -> u64 {
+        (amount as u128 * self.bps as u128 / BPS_DENOMINATOR as u128) as u64
+    }
+}
+
+// Adds each recipient's cut of `amount` to its balance and returns the total.
+fn accrue_fees(fee_splits: &mut [FeeSplit], amount: u64) -> Option<u64> {
+    fee_splits.iter_mut().try_fold(0u64, |total, split| {
+        let fee = split.fee(amount);
+        split.accrued = split.accrued.checked_add(fee)?;
+        total.checked_add(fee)
+    })
 }
 
-fn validate_ticket_numbers(numbers: &[u8; 7]) -> bool {
+// Builds the new fee table, carrying over what kept recipients are still owed,
+// and returns the unswept total of recipients that were dropped.
+fn carry_over_fees(
+    current: &[FeeSplit],
+    fee_splits: &[FeeSplitInfo],
+) -> Option<(Vec<FeeSplit>, u64)> {
+    let accrued = |recipient: Pubkey| {
+        current
+            .iter()
+            .find(|split| split.recipient == recipient)
+            .map(|split| split.accrued)
+    };
+    let splits = fee_splits
+        .iter()
+        .map(|split| FeeSplit {
+            recipient: split.recipient,
+            bps: split.bps,
+            accrued: accrued(split.recipient).unwrap_or(0),
+        })
+        .collect();
+    let released = current
+        .iter()
+        .filter(|split| {
+            fee_splits
+                .iter()
+                .all(|kept| kept.recipient != split.recipient)
+        })
+        .try_fold(0u64, |total, split| total.checked_add(split.accrued))?;
+    Some((splits, released))
+}
+
+fn validate_selection(reds: &[u8], blues: &[u8]) -> bool {
     let mut used_reds = std::collections::HashSet::new();
+    let mut used_blues = std::collections::HashSet::new();
+
+    (6..=MAX_COMPOUND_REDS).contains(&reds.len())
+        && (1..=MAX_COMPOUND_BLUES).contains(&blues.len())
+        && reds
+            .iter()
+            .all(|&num| (1..=33).contains(&num) && used_reds.insert(num))
+        && blues
+            .iter()
+            .all(|&num| (1..=16).contains(&num) && used_blues.insert(num))
+}
+
+fn prize_tier(red_matches: u64, blue_match: bool) -> Option<usize> {
+    match (red_matches, blue_match) {
+        (6, true) => Some(0),
+        (6, false) => Some(1),
+        (5, true) => Some(2),
+        (5, false) | (4, true) => Some(3),
+        (4, false) | (3, true) => Some(4),
+        (_, true) => Some(5),
+        _ => None,
+    }
+}
+
+fn combinations(n: u64, k: u64) -> u64 {
+    if k > n {
+        return 0;
+    }
+    (0..k).fold(1, |acc, i| acc * (n - i) / (i + 1))
+}
+
+// Every 6-red combination is bought once with every chosen blue.
+fn compound_lines(reds: &[u8], blues: &[u8]) -> Option<u64> {
+    combinations(reds.len() as u64, 6).checked_mul(blues.len() as u64)
+}
+
+// Counts the winning 6+1 lines per tier implied by a (possibly compound) selection.
+fn winning_lines(reds: &[u8], blues: &[u8], draw_numbers: &[u8; 7]) -> [u64; PRIZE_TIERS] {
+    let red_hits = reds
+        .iter()
+        .filter(|num| draw_numbers[..6].contains(num))
+        .count() as u64;
+    let red_misses = reds.len() as u64 - red_hits;
+    let blue_hits = blues.contains(&draw_numbers[6]) as u64;
+    let blue_misses = blues.len() as u64 - blue_hits;
+
+    let mut lines = [0u64; PRIZE_TIERS];
+    for red_matches in 0..=6 {
+        let red_lines =
+            combinations(red_hits, red_matches) * combinations(red_misses, 6 - red_matches);
+        if let Some(tier) = prize_tier(red_matches, true) {
+            lines[tier] += red_lines * blue_hits;
+        }
+        if let Some(tier) = prize_tier(red_matches, false) {
+            lines[tier] += red_lines * blue_misses;
+        }
+    }
+    lines
+}
 
-    for &num in numbers.iter().take(6) {
-        if num < 1 || num > 33 || !used_reds.insert(num) {
-            return false;
+fn enter_ticket(
+    lottery: &mut Account<LotteryState>,
+    round: &mut Account<Round>,
+    ticket: &mut Account<Ticket>,
+    amount: u64,
+    now: i64,
+) -> Result<()> {
+    let round_id = lottery.ticket_round(now);
+    let sales_closed = round_id != lottery.current_round;
+
+    ticket.lottery = lottery.key();
+    ticket.round_id = round_id;
+    ticket.ticket_id = lottery.ticket_count;
+    ticket.amount = amount;
+    ticket.purchase_time = now;
+
+    let (reserve_share, mut house_share) = lottery.revenue_split.shares(amount);
+    // Partner fees come out of the house share, never the prize pools.
+    let fees = accrue_fees(&mut lottery.fee_splits, amount).ok_or(CustomError::ArithmeticError)?;
+    house_share = house_share
+        .checked_sub(fees)
+        .ok_or(CustomError::ArithmeticError)?;
+    lottery.reserve_pool = lottery
+        .reserve_pool
+        .checked_add(reserve_share)
+        .ok_or(CustomError::ArithmeticError)?;
+    lottery.house_revenue = lottery
+        .house_revenue
+        .checked_add(house_share)
+        .ok_or(CustomError::ArithmeticError)?;
+
+    let jackpot_share =
+        (amount as u128 * lottery.jackpot_share_bps as u128 / BPS_DENOMINATOR as u128) as u64;
+    // What is left after the reserve, house and jackpot shares funds this
+    // round's tier pools and fixed prizes.
+    let prize_share = amount
+        .checked_sub(reserve_share)
+        .and_then(|rest| rest.checked_sub(house_share))
+        .and_then(|rest| rest.checked_sub(fees))
+        .and_then(|rest| rest.checked_sub(jackpot_share))
+        .ok_or(CustomError::ArithmeticError)?;
+    lottery.open_prize_pool = lottery
+        .open_prize_pool
+        .checked_add(prize_share)
+        .ok_or(CustomError::ArithmeticError)?;
+
+    if sales_closed {
+        lottery.next_round_jackpot = lottery
+            .next_round_jackpot
+            .checked_add(jackpot_share)
+            .ok_or(CustomError::ArithmeticError)?;
+        lottery.next_round_ticket_count = lottery
+            .next_round_ticket_count
+            .checked_add(1)
+            .ok_or(CustomError::ArithmeticError)?;
+        lottery.next_round_amount = lottery
+            .next_round_amount
+            .checked_add(amount)
+            .ok_or(CustomError::ArithmeticError)?;
+        lottery.next_round_prize_pool = lottery
+            .next_round_prize_pool
+            .checked_add(prize_share)
+            .ok_or(CustomError::ArithmeticError)?;
+    } else {
+        lottery.jackpot_pool = lottery
+            .jackpot_pool
+            .checked_add(jackpot_share)
+            .ok_or(CustomError::ArithmeticError)?;
+        round.ticket_count = round
+            .ticket_count
+            .checked_add(1)
+            .ok_or(CustomError::ArithmeticError)?;
+        round.total_amount = round
+            .total_amount
+            .checked_add(amount)
+            .ok_or(CustomError::ArithmeticError)?;
+        round.prize_pool = round
+            .prize_pool
+            .checked_add(prize_share)
+            .ok_or(CustomError::ArithmeticError)?;
+    }
+
+    lottery.ticket_count = lottery
+        .ticket_count
+        .checked_add(1)
+        .ok_or(CustomError::ArithmeticError)?;
+
+    Ok(())
+}
+
+fn find_draw_slot_hash(slot_hashes: &[u8], target_slot: u64) -> Option<(u64, [u8; 32])> {
+    let count = u64::from_le_bytes(slot_hashes.get(..8)?.try_into().ok()?) as usize;
+    let mut found = None;
+
+    // Newest entries come first. A skipped target falls through to the next
+    // recorded slot, but only when an older entry shows nothing was evicted.
+    for entry in slot_hashes[8..].chunks_exact(40).take(count) {
+        let slot = u64::from_le_bytes(entry[..8].try_into().unwrap());
+        if slot < target_slot {
+            return found;
+        }
+        found = Some((slot, entry[8..].try_into().unwrap()));
+        if slot == target_slot {
+            return found;
+        }
+    }
+
+    None
+}
+
+fn validate_prize_tiers(
+    prize_tiers: &[PrizeTier; PRIZE_TIERS],
+    jackpot_share_bps: u16,
+    prize_bps: u16,
+) -> bool {
+    let total_bps: u64 = prize_tiers.iter().map(|tier| tier.pool_bps as u64).sum();
+    // The jackpot tier is always pari-mutuel, even when it lives off the jackpot alone.
+    let pari_mutuel_count = 1 + prize_tiers[1..]
+        .iter()
+        .take_while(|tier| tier.pool_bps > 0)
+        .count();
+
+    prize_tiers[0].fixed_prize == 0
+        && total_bps + jackpot_share_bps as u64 <= prize_bps as u64
+        && prize_tiers[pari_mutuel_count..]
+            .iter()
+            .all(|tier| tier.pool_bps == 0)
+}
+
+// Tops the jackpot up to the guaranteed minimum, as far as the reserve allows.
+// Splits a prize between what is left of the round's prize share, the reserve
+// and house revenue, in that order; whatever none of them covers stays owed.
+fn fund_prize(prize: u64, pool_left: u64, reserve: u64, house: u64) -> (u64, u64, u64) {
+    let from_pool = prize.min(pool_left);
+    let from_reserve = (prize - from_pool).min(reserve);
+    let from_house = (prize - from_pool - from_reserve).min(house);
+    (from_pool, from_reserve, from_house)
+}
+
+fn jackpot_top_up(min_jackpot: u64, jackpot_pool: u64, reserve_pool: u64) -> u64 {
+    min_jackpot.saturating_sub(jackpot_pool).min(reserve_pool)
+}
+
+fn ticket_prize(round: &Round, ticket: &Ticket) -> Option<u64> {
+    // Tickets of a voided round get back what they paid.
+    if round.is_voided {
+        return Some(ticket.amount);
+    }
+
+    let mut prize = 0u128;
+    for tier in 0..PRIZE_TIERS {
+        let wins = ticket.tier_wins[tier] as u128;
+        if wins == 0 {
+            continue;
         }
+
+        let tier_prize = if tier == 0 || round.prize_tiers[tier].pool_bps > 0 {
+            (round.tier_pools[tier] as u128 * wins).checked_div(round.tier_winners[tier] as u128)?
+        } else {
+            round.prize_tiers[tier].fixed_prize as u128 * wins
+        };
+        prize = prize.checked_add(tier_prize)?;
     }
-    numbers[6] >= 1 && numbers[6] <= 16
+    u64::try_from(prize).ok()
+}
+
+// Validates a batch entry against the finalized round; returns false when
+// the ticket was already paid, by an earlier batch or a claim.
+fn check_payout(
+    lottery: &Pubkey,
+    round: &Round,
+    ticket: &Ticket,
+    transfer: &TransferInfo,
+) -> Result<bool> {
+    require!(
+        ticket.lottery == *lottery
+            && ticket.round_id == round.round_id
+            && ticket.ticket_id == transfer.ticket_id,
+        CustomError::InvalidPayoutTicket
+    );
+    require!(
+        ticket.buyer == transfer.recipient,
+        CustomError::RecipientOwnerMismatch
+    );
+    require!(round.is_finalized, CustomError::RoundNotFinalized);
+    require!(
+        ticket.is_settled || round.is_voided,
+        CustomError::TicketNotSettled
+    );
+    require!(
+        ticket_prize(round, ticket) == Some(transfer.amount),
+        CustomError::PayoutAmountMismatch
+    );
+    Ok(!ticket.is_paid)
+}
+
+// Accounts are only created at the recipient's associated token address.
+fn check_recipient_ata(
+    wallet: &Pubkey,
+    token_account: &Pubkey,
+    recipient: &Pubkey,
+    mint: &Pubkey,
+) -> Result<()> {
+    require!(
+        wallet == recipient
+            && *token_account == associated_token::get_associated_token_address(recipient, mint),
+        CustomError::InvalidAssociatedTokenAccount
+    );
+    Ok(())
+}
+
+fn generate_draw_random_number(
+    secret: &[u8; 32],
+    slot_hash: &[u8; 32],
+    lottery: &Pubkey,
+    round_id: u64,
+) -> [u8; 32] {
+    let mut data = Vec::with_capacity(104);
+    data.extend_from_slice(secret);
+    data.extend_from_slice(slot_hash);
+    data.extend_from_slice(lottery.as_ref());
+    data.extend_from_slice(&round_id.to_le_bytes());
+    hash(&data).to_bytes()
+}
+
+fn quick_pick_seed(
+    lottery: &Pubkey,
+    ticket_id: u64,
+    buyer: &Pubkey,
+    clock: &Clock,
+    line: usize,
+) -> [u8; 32] {
+    // The buyer can influence this, which is harmless: no pick is luckier than another.
+    let mut data = Vec::with_capacity(92);
+    data.extend_from_slice(lottery.as_ref());
+    data.extend_from_slice(&ticket_id.to_le_bytes());
+    data.extend_from_slice(buyer.as_ref());
+    data.extend_from_slice(&clock.slot.to_le_bytes());
+    data.extend_from_slice(&clock.unix_timestamp.to_le_bytes());
+    data.extend_from_slice(&(line as u32).to_le_bytes());
+    hash(&data).to_bytes()
 }
 
-fn generate_vrf_random_number(
-    timestamp: i64,
-    recent_blockhashes: &[u8],
-    lottery_account: &AccountInfo,
-    uuid: &str,
-) -> Result<[u8; 32]> {
-    let mut data = Vec::with_capacity(512);
-    data.extend_from_slice(uuid.as_bytes());
-    data.extend_from_slice(&timestamp.to_le_bytes());
-    data.extend_from_slice(recent_blockhashes);
-    data.extend_from_slice(&lottery_account.data.borrow());
-    data.extend_from_slice(&lottery_account.lamports().to_le_bytes());
+// Byte stream over a draw hash, extended with hash(seed, counter) once the
+// first 32 bytes run out.
+struct RandomStream {
+    seed: [u8; 32],
+    block: [u8; 32],
+    index: usize,
+    counter: u32,
+}
 
-    if let Ok(clock) = Clock::get() {
-        data.extend_from_slice(&clock.slot.to_le_bytes());
+impl RandomStream {
+    fn new(seed: &[u8; 32]) -> Self {
+        Self {
+            seed: *seed,
+            block: *seed,
+            index: 0,
+            counter: 0,
+        }
     }
 
-    let mut final_hash = hash(&data).to_bytes();
-    for _ in 0..3 {
-        final_hash = hash(&final_hash).to_bytes();
+    fn next_byte(&mut self) -> u8 {
+        if self.index == self.block.len() {
+            self.counter += 1;
+            self.block = hashv(&[&self.seed, &self.counter.to_le_bytes()]).to_bytes();
+            self.index = 0;
+        }
+        self.index += 1;
+        self.block[self.index - 1]
     }
 
-    Ok(final_hash)
+    // Uniform in 0..n: bytes past the largest multiple of n are rejected.
+    fn uniform(&mut self, n: u8) -> u8 {
+        let limit = 256 - 256 % n as u16;
+        loop {
+            let byte = self.next_byte();
+            if (byte as u16) < limit {
+                return byte % n;
+            }
+        }
+    }
 }
 
 fn convert_random_to_numbers(random_value: &[u8; 32]) -> [u8; 7] {
+    let mut stream = RandomStream::new(random_value);
     let mut numbers = [0u8; 7];
-    let mut used_reds = std::collections::HashSet::new();
 
-    for i in 0..6 {
-        let mut val = (random_value[i] as u16 % 33 + 1) as u8;
-        while used_reds.contains(&val) {
-            val = (val % 33) + 1;
-        }
-        used_reds.insert(val);
-        numbers[i] = val;
+    // Draw reds without replacement, like balls leaving the drum.
+    let mut reds: Vec<u8> = (1..=33).collect();
+    for number in numbers.iter_mut().take(6) {
+        let index = stream.uniform(reds.len() as u8) as usize;
+        *number = reds.swap_remove(index);
     }
 
-    numbers[6] = (random_value[6] % 16 + 1) as u8;
+    numbers[6] = stream.uniform(16) + 1;
 
     numbers
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // About 4.2M draws: every 6-red set is then expected ~3.8 times, and
+    // single balls, pairs and ball/blue cells thousands of times each.
+    const DRAWS: u64 = 1 << 22;
+
+    fn draws() -> impl Iterator<Item = &'static [u8; 7]> {
+        static CACHE: std::sync::OnceLock<Vec<[u8; 7]>> = std::sync::OnceLock::new();
+        CACHE
+            .get_or_init(|| {
+                (0..DRAWS)
+                    .map(|i| convert_random_to_numbers(&hash(&i.to_le_bytes()).to_bytes()))
+                    .collect()
+            })
+            .iter()
+    }
+
+    fn chi_squared(observed: &[u64], expected: f64) -> f64 {
+        observed
+            .iter()
+            .map(|&count| (count as f64 - expected).powi(2) / expected)
+            .sum()
+    }
+
+    // Wilson-Hilferty approximation of the chi-squared quantile at p = 1e-6.
+    fn critical_value(degrees_of_freedom: usize) -> f64 {
+        let k = degrees_of_freedom as f64;
+        let z = 4.753;
+        k * (1.0 - 2.0 / (9.0 * k) + z * (2.0 / (9.0 * k)).sqrt()).powi(3)
+    }
+
+    #[test]
+    fn draws_are_valid() {
+        for numbers in draws().take(10_000) {
+            assert!(validate_selection(&numbers[..6], &numbers[6..]));
+        }
+    }
+
+    #[test]
+    fn exhausted_hash_falls_back_to_expansion() {
+        // 0xff is rejected for every red ball range, so all reds come from the expansion.
+        let numbers = convert_random_to_numbers(&[0xff; 32]);
+        assert!(validate_selection(&numbers[..6], &numbers[6..]));
+    }
+
+    #[test]
+    fn red_balls_are_uniform() {
+        let mut counts = [0u64; 33];
+        for numbers in draws() {
+            for &red in &numbers[..6] {
+                counts[red as usize - 1] += 1;
+            }
+        }
+
+        let expected = (DRAWS * 6) as f64 / 33.0;
+        assert!(chi_squared(&counts, expected) < critical_value(32));
+    }
+
+    #[test]
+    fn blue_balls_are_uniform() {
+        let mut counts = [0u64; 16];
+        for numbers in draws() {
+            counts[numbers[6] as usize - 1] += 1;
+        }
+
+        let expected = DRAWS as f64 / 16.0;
+        assert!(chi_squared(&counts, expected) < critical_value(15));
+    }
+
+    #[test]
+    fn red_ball_pairs_are_uniform() {
+        // Each draw covers C(6,2) of the C(33,2) red pairs; a biased walk
+        // over neighbouring balls shows up here even when singles look flat.
+        let mut counts = vec![0u64; 33 * 33];
+        for numbers in draws() {
+            for a in 0..6 {
+                for b in a + 1..6 {
+                    let (low, high) = if numbers[a] < numbers[b] {
+                        (numbers[a], numbers[b])
+                    } else {
+                        (numbers[b], numbers[a])
+                    };
+                    counts[(low as usize - 1) * 33 + high as usize - 1] += 1;
+                }
+            }
+        }
+
+        let pairs: Vec<u64> = (0..33)
+            .flat_map(|low| (low + 1..33).map(move |high| (low, high)))
+            .map(|(low, high)| counts[low * 33 + high])
+            .collect();
+        let expected = (DRAWS * 15) as f64 / pairs.len() as f64;
+        assert_eq!(pairs.len(), 528);
+        assert!(chi_squared(&pairs, expected) < critical_value(527));
+    }
+
+    #[test]
+    fn red_combinations_are_equally_likely() {
+        // Each sorted 6-red set is indexed by the combinatorial number system.
+        let total = combinations(33, 6) as usize;
+        let mut counts = vec![0u64; total];
+        for numbers in draws() {
+            let mut reds = [0u8; 6];
+            reds.copy_from_slice(&numbers[..6]);
+            reds.sort_unstable();
+            let index: u64 = reds
+                .iter()
+                .enumerate()
+                .map(|(i, &red)| combinations(red as u64 - 1, i as u64 + 1))
+                .sum();
+            counts[index as usize] += 1;
+        }
+
+        let expected = DRAWS as f64 / total as f64;
+        assert_eq!(total, 1_107_568);
+        assert!(chi_squared(&counts, expected) < critical_value(total - 1));
+    }
+
+    #[test]
+    fn blue_ball_is_independent_of_reds() {
+        // One cell per draw (first red, blue) keeps the counts independent.
+        let mut counts = [0u64; 33 * 16];
+        for numbers in draws() {
+            counts[(numbers[0] as usize - 1) * 16 + numbers[6] as usize - 1] += 1;
+        }
+
+        let expected = DRAWS as f64 / counts.len() as f64;
+        assert!(chi_squared(&counts, expected) < critical_value(counts.len() - 1));
+    }
+
+    fn tiers(
+        pool_bps: [u16; PRIZE_TIERS],
+        fixed_prize: [u64; PRIZE_TIERS],
+    ) -> [PrizeTier; PRIZE_TIERS] {
+        let mut prize_tiers = [PrizeTier::default(); PRIZE_TIERS];
+        for tier in 0..PRIZE_TIERS {
+            prize_tiers[tier] = PrizeTier {
+                fixed_prize: fixed_prize[tier],
+                pool_bps: pool_bps[tier],
+            };
+        }
+        prize_tiers
+    }
+
+    #[test]
+    fn matches_map_to_prize_tiers() {
+        assert_eq!(prize_tier(6, true), Some(0));
+        assert_eq!(prize_tier(6, false), Some(1));
+        assert_eq!(prize_tier(5, true), Some(2));
+        assert_eq!(prize_tier(5, false), Some(3));
+        assert_eq!(prize_tier(4, true), Some(3));
+        assert_eq!(prize_tier(4, false), Some(4));
+        assert_eq!(prize_tier(3, true), Some(4));
+        assert_eq!(prize_tier(0, true), Some(5));
+        assert_eq!(prize_tier(3, false), None);
+        assert_eq!(prize_tier(0, false), None);
+    }
+
+    #[test]
+    fn single_line_wins_one_tier() {
+        let draw = [1, 2, 3, 4, 5, 6, 7];
+        assert_eq!(
+            winning_lines(&[1, 2, 3, 4, 5, 6], &[7], &draw),
+            [1, 0, 0, 0, 0, 0]
+        );
+        assert_eq!(
+            winning_lines(&[1, 2, 3, 4, 5, 33], &[8], &draw),
+            [0, 0, 0, 1, 0, 0]
+        );
+        assert_eq!(
+            winning_lines(&[10, 11, 12, 13, 14, 15], &[8], &draw),
+            [0; PRIZE_TIERS]
+        );
+    }
+
+    #[test]
+    fn compound_tickets_price_every_combination() {
+        let reds: Vec<u8> = (1..=33).collect();
+        let blues: Vec<u8> = (1..=16).collect();
+        assert_eq!(compound_lines(&reds[..6], &blues[..1]), Some(1));
+        assert_eq!(compound_lines(&reds[..7], &blues[..1]), Some(7));
+        assert_eq!(compound_lines(&reds[..10], &blues[..3]), Some(210 * 3));
+        assert_eq!(
+            compound_lines(&reds[..MAX_COMPOUND_REDS], &blues),
+            Some(38760 * 16)
+        );
+    }
+
+    #[test]
+    fn compound_selection_counts_every_winning_line() {
+        // 8 reds holding all six drawn and 2 blues holding the drawn one give
+        // C(8,6) * 2 = 56 lines, each matching at least four reds.
+        let draw = [1, 2, 3, 4, 5, 6, 7];
+        let lines = winning_lines(&[1, 2, 3, 4, 5, 6, 20, 21], &[7, 8], &draw);
+        assert_eq!(lines, [1, 1, 12, 12 + 15, 15, 0]);
+        assert_eq!(lines.iter().sum::<u64>(), 56);
+    }
+
+    #[test]
+    fn prize_tiers_must_fit_the_prize_share() {
+        let prize_tiers = tiers([2000, 1000, 0, 0, 0, 0], [0, 0, 3000, 200, 10, 5]);
+        assert!(validate_prize_tiers(&prize_tiers, 1000, 5000));
+        assert!(validate_prize_tiers(&prize_tiers, 2000, 5000));
+        assert!(!validate_prize_tiers(&prize_tiers, 2001, 5000));
+        assert!(!validate_prize_tiers(&prize_tiers, 0, 2999));
+
+        // A fixed jackpot, or a pool tier after a fixed one, is rejected.
+        let fixed_jackpot = tiers([0; PRIZE_TIERS], [1000, 0, 0, 0, 0, 0]);
+        assert!(!validate_prize_tiers(&fixed_jackpot, 0, 10000));
+        let gap = tiers([2000, 0, 500, 0, 0, 0], [0; PRIZE_TIERS]);
+        assert!(!validate_prize_tiers(&gap, 0, 10000));
+    }
+
+    #[test]
+    fn jackpot_is_topped_up_from_the_reserve() {
+        assert_eq!(jackpot_top_up(1000, 400, 10000), 600);
+        assert_eq!(jackpot_top_up(1000, 400, 250), 250);
+        assert_eq!(jackpot_top_up(1000, 1500, 10000), 0);
+        assert_eq!(jackpot_top_up(0, 0, 10000), 0);
+    }
+
+    // 2024-01-04, a Thursday.
+    const THURSDAY: i64 = 19_726;
+
+    fn thursday_draws(draw_time: u32, utc_offset: i32, skipped_days: Vec<u32>) -> LotteryState {
+        LotteryState {
+            schedule: DrawSchedule {
+                weekday_mask: 1 << 4,
+                draw_time,
+                utc_offset,
+                draw_window: 3600,
+                sales_close: 600,
+            },
+            skipped_days,
+            ..LotteryState::default()
+        }
+    }
+
+    #[test]
+    fn draw_days_follow_the_weekday_mask_from_a_thursday_epoch() {
+        let lottery = thursday_draws(72_000, 0, vec![]);
+        assert_eq!(lottery.scheduled_draw_on(0), Some(72_000));
+        assert_eq!(lottery.scheduled_draw_on(1), None);
+        assert_eq!(lottery.scheduled_draw_on(-1), None);
+        assert_eq!(
+            lottery.scheduled_draw_on(-7),
+            Some(-7 * SECONDS_PER_DAY + 72_000)
+        );
+        assert_eq!(
+            lottery.scheduled_draw_on(THURSDAY),
+            Some(THURSDAY * SECONDS_PER_DAY + 72_000)
+        );
+
+        // Tuesday, Thursday and Saturday.
+        let lottery = LotteryState {
+            schedule: DrawSchedule {
+                weekday_mask: (1 << 2) | (1 << 4) | (1 << 6),
+                ..lottery.schedule
+            },
+            ..lottery
+        };
+        let draw_days: Vec<i64> = (THURSDAY..THURSDAY + 7)
+            .filter(|&day| lottery.scheduled_draw_on(day).is_some())
+            .collect();
+        assert_eq!(draw_days, vec![THURSDAY, THURSDAY + 2, THURSDAY + 5]);
+    }
+
+    #[test]
+    fn draws_near_local_midnight_land_on_the_right_utc_day() {
+        // 00:05 in UTC+8 is 16:05 UTC the day before.
+        let lottery = thursday_draws(300, 8 * 3600, vec![]);
+        let draw = THURSDAY * SECONDS_PER_DAY - 8 * 3600 + 300;
+        assert_eq!(lottery.scheduled_draw_on(THURSDAY), Some(draw));
+        assert_eq!(lottery.current_draw_time(draw), Some(draw));
+        // Sales close at 23:55 local on Wednesday.
+        assert_eq!(lottery.pending_draw_time(draw - 600), Some(draw));
+
+        // 23:00 in UTC-5 is 04:00 UTC the day after; the window runs past local midnight.
+        let lottery = thursday_draws(82_800, -5 * 3600, vec![]);
+        let draw = (THURSDAY + 1) * SECONDS_PER_DAY + 4 * 3600;
+        assert_eq!(lottery.scheduled_draw_on(THURSDAY), Some(draw));
+        assert_eq!(lottery.current_draw_time(draw + 3600), Some(draw));
+        assert_eq!(lottery.current_draw_time(draw + 3601), None);
+    }
+
+    #[test]
+    fn skipped_days_have_no_draw() {
+        let lottery = thursday_draws(72_000, 0, vec![THURSDAY as u32]);
+        let draw = THURSDAY * SECONDS_PER_DAY + 72_000;
+        assert_eq!(lottery.scheduled_draw_on(THURSDAY), None);
+        assert_eq!(lottery.current_draw_time(draw), None);
+        assert_eq!(lottery.pending_draw_time(draw - 600), None);
+        assert_eq!(
+            lottery.scheduled_draw_on(THURSDAY + 7),
+            Some(draw + 7 * SECONDS_PER_DAY)
+        );
+    }
+
+    #[test]
+    fn draw_and_sales_close_windows_include_their_bounds() {
+        let mut lottery = thursday_draws(72_000, 0, vec![]);
+        let draw = THURSDAY * SECONDS_PER_DAY + 72_000;
+
+        assert_eq!(lottery.current_draw_time(draw - 1), None);
+        assert_eq!(lottery.current_draw_time(draw), Some(draw));
+        assert_eq!(lottery.current_draw_time(draw + 3600), Some(draw));
+        assert_eq!(lottery.current_draw_time(draw + 3601), None);
+
+        assert_eq!(lottery.pending_draw_time(draw - 601), None);
+        assert_eq!(lottery.pending_draw_time(draw - 600), Some(draw));
+        assert_eq!(lottery.pending_draw_time(draw + 3600), Some(draw));
+
+        lottery.last_draw_time = draw;
+        assert_eq!(lottery.pending_draw_time(draw - 600), None);
+    }
+
+    #[test]
+    fn tickets_roll_forward_only_while_a_draw_is_pending() {
+        let mut lottery = LotteryState {
+            current_round: 5,
+            ..thursday_draws(72_000, 0, vec![])
+        };
+        let draw = THURSDAY * SECONDS_PER_DAY + 72_000;
+
+        assert_eq!(lottery.ticket_round(draw - 601), 5);
+        assert_eq!(lottery.ticket_round(draw - 600), 6);
+        assert_eq!(lottery.ticket_round(draw - 1), 6);
+        assert_eq!(lottery.ticket_round(draw + 60), 6);
+        // The window passed without a draw.
+        assert_eq!(lottery.ticket_round(draw + 3601), 5);
+
+        // Once drawn, round 6 is current and takes the rest of the window.
+        lottery.last_draw_time = draw + 60;
+        lottery.current_round = 6;
+        assert_eq!(lottery.ticket_round(draw + 120), 6);
+        assert_eq!(lottery.ticket_round(draw + 3601), 6);
+    }
+
+    #[test]
+    fn schedule_validation_bounds_each_field() {
+        let schedule = thursday_draws(72_000, 0, vec![]).schedule;
+        assert!(schedule.is_valid());
+        assert!(DrawSchedule {
+            weekday_mask: 0x7f,
+            draw_time: 86_399,
+            utc_offset: -86_399,
+            draw_window: 86_399,
+            sales_close: 86_399,
+        }
+        .is_valid());
+
+        let invalid = [
+            DrawSchedule {
+                weekday_mask: 0,
+                ..schedule
+            },
+            DrawSchedule {
+                weekday_mask: 0x80 | 1,
+                ..schedule
+            },
+            DrawSchedule {
+                draw_time: 86_400,
+                ..schedule
+            },
+            DrawSchedule {
+                draw_window: 0,
+                ..schedule
+            },
+            DrawSchedule {
+                draw_window: 86_400,
+                ..schedule
+            },
+            DrawSchedule {
+                sales_close: 86_400,
+                ..schedule
+            },
+            DrawSchedule {
+                utc_offset: 86_400,
+                ..schedule
+            },
+            DrawSchedule {
+                utc_offset: -86_400,
+                ..schedule
+            },
+        ];
+        for schedule in invalid {
+            assert!(!schedule.is_valid());
+        }
+    }
+
+    #[test]
+    fn ticket_prize_splits_pools_and_pays_fixed_tiers() {
+        let round = Round {
+            prize_tiers: tiers([2000, 1000, 0, 0, 0, 0], [0, 0, 3000, 200, 10, 5]),
+            tier_winners: [3, 2, 1, 4, 0, 10],
+            tier_pools: [1000, 500, 0, 0, 0, 0],
+            ..Round::default()
+        };
+
+        let ticket = Ticket {
+            tier_wins: [1, 1, 0, 2, 0, 3],
+            ..Ticket::default()
+        };
+        // 1000 / 3 + 500 / 2 + 2 * 200 + 3 * 5, with pool shares rounded down.
+        assert_eq!(ticket_prize(&round, &ticket), Some(333 + 250 + 400 + 15));
+
+        let ticket = Ticket {
+            tier_wins: [3, 0, 0, 0, 0, 0],
+            ..Ticket::default()
+        };
+        assert_eq!(ticket_prize(&round, &ticket), Some(1000));
+        assert_eq!(ticket_prize(&round, &Ticket::default()), Some(0));
+
+        let round = Round {
+            is_voided: true,
+            ..round
+        };
+        let ticket = Ticket {
+            amount: 700,
+            tier_wins: [1, 0, 0, 0, 0, 0],
+            ..Ticket::default()
+        };
+        assert_eq!(ticket_prize(&round, &ticket), Some(700));
+    }
+
+    // SlotHashes sysvar data, newest slot first, with each hash filled with its slot.
+    fn slot_hashes(slots: &[u64]) -> Vec<u8> {
+        let mut data = (slots.len() as u64).to_le_bytes().to_vec();
+        for &slot in slots {
+            data.extend_from_slice(&slot.to_le_bytes());
+            data.extend_from_slice(&[slot as u8; 32]);
+        }
+        data
+    }
+
+    #[test]
+    fn draw_slot_hash_is_the_target_or_the_next_recorded_slot() {
+        let data = slot_hashes(&[105, 103, 102, 100]);
+
+        assert_eq!(find_draw_slot_hash(&data, 103), Some((103, [103; 32])));
+        assert_eq!(find_draw_slot_hash(&data, 100), Some((100, [100; 32])));
+        // 101 was skipped; 100 proves nothing between it and 102 was evicted.
+        assert_eq!(find_draw_slot_hash(&data, 101), Some((102, [102; 32])));
+        assert_eq!(find_draw_slot_hash(&data, 104), Some((105, [105; 32])));
+        // Not reached yet.
+        assert_eq!(find_draw_slot_hash(&data, 106), None);
+    }
+
+    #[test]
+    fn evicted_draw_slot_hash_is_not_replaced() {
+        // Every entry is newer than the target, which may have been recorded
+        // and evicted, so no later hash may stand in for it.
+        let data = slot_hashes(&[105, 103, 102]);
+        assert_eq!(find_draw_slot_hash(&data, 101), None);
+        assert_eq!(find_draw_slot_hash(&data, 99), None);
+
+        assert_eq!(find_draw_slot_hash(&slot_hashes(&[]), 101), None);
+        assert_eq!(find_draw_slot_hash(&[], 101), None);
+    }
+
+    #[test]
+    fn payouts_match_finalized_prizes_and_skip_paid_tickets() {
+        let lottery = Pubkey::new_unique();
+        let buyer = Pubkey::new_unique();
+        let mut round = Round {
+            lottery,
+            round_id: 4,
+            prize_tiers: tiers([0; 6], [0, 0, 3000, 200, 10, 5]),
+            ..Round::default()
+        };
+        let mut ticket = Ticket {
+            lottery,
+            buyer,
+            round_id: 4,
+            ticket_id: 9,
+            tier_wins: [0, 0, 0, 1, 0, 2],
+            is_settled: true,
+            ..Ticket::default()
+        };
+        let transfer = TransferInfo {
+            recipient: buyer,
+            ticket_id: 9,
+            amount: 210,
+        };
+
+        let err = check_payout(&lottery, &round, &ticket, &transfer).unwrap_err();
+        assert_eq!(err, CustomError::RoundNotFinalized.into());
+
+        round.is_finalized = true;
+        assert!(check_payout(&lottery, &round, &ticket, &transfer).unwrap());
+
+        let inflated = TransferInfo {
+            amount: 211,
+            ..transfer
+        };
+        let err = check_payout(&lottery, &round, &ticket, &inflated).unwrap_err();
+        assert_eq!(err, CustomError::PayoutAmountMismatch.into());
+
+        let other = TransferInfo {
+            recipient: Pubkey::new_unique(),
+            ..transfer
+        };
+        let err = check_payout(&lottery, &round, &ticket, &other).unwrap_err();
+        assert_eq!(err, CustomError::RecipientOwnerMismatch.into());
+
+        let err = check_payout(&Pubkey::new_unique(), &round, &ticket, &transfer).unwrap_err();
+        assert_eq!(err, CustomError::InvalidPayoutTicket.into());
+
+        ticket.is_paid = true;
+        assert!(!check_payout(&lottery, &round, &ticket, &transfer).unwrap());
+
+        // Tickets of a voided round are refunded without being settled.
+        let round = Round {
+            is_voided: true,
+            ..round
+        };
+        let ticket = Ticket {
+            amount: 500,
+            is_settled: false,
+            is_paid: false,
+            ..ticket
+        };
+        let refund = TransferInfo {
+            amount: 500,
+            ..transfer
+        };
+        assert!(check_payout(&lottery, &round, &ticket, &refund).unwrap());
+    }
+
+    #[test]
+    fn payout_accounts_are_created_at_the_associated_address() {
+        let recipient = Pubkey::new_unique();
+        let mint = Pubkey::new_unique();
+        let (ata, _) = Pubkey::find_program_address(
+            &[recipient.as_ref(), token::ID.as_ref(), mint.as_ref()],
+            &associated_token::ID,
+        );
+        assert!(check_recipient_ata(&recipient, &ata, &recipient, &mint).is_ok());
+
+        let other_mint =
+            associated_token::get_associated_token_address(&recipient, &Pubkey::new_unique());
+        let err = check_recipient_ata(&recipient, &other_mint, &recipient, &mint).unwrap_err();
+        assert_eq!(err, CustomError::InvalidAssociatedTokenAccount.into());
+
+        let err = check_recipient_ata(&Pubkey::new_unique(), &ata, &recipient, &mint).unwrap_err();
+        assert_eq!(err, CustomError::InvalidAssociatedTokenAccount.into());
+    }
+
+    #[test]
+    fn revenue_split_shares_round_down_and_leave_room_for_fees() {
+        let split = RevenueSplit {
+            prize_bps: 6000,
+            reserve_bps: 1500,
+            house_bps: 2500,
+        };
+        assert!(split.is_valid());
+        assert!(!RevenueSplit {
+            house_bps: 2499,
+            ..split
+        }
+        .is_valid());
+        assert!(!RevenueSplit::default().is_valid());
+
+        // 15% and 25% of 999, rounded down; the prize share keeps the remainder.
+        assert_eq!(split.shares(999), (149, 249));
+        // Shares are computed in u128, so the full u64 range does not overflow.
+        assert_eq!(
+            split.shares(u64::MAX),
+            (2_767_011_611_056_432_742, 4_611_686_018_427_387_903)
+        );
+
+        // Partner fees come out of the house share only.
+        assert!(split.covers_fees(2500));
+        assert!(!split.covers_fees(2501));
+    }
+
+    #[test]
+    fn fees_round_down_and_survive_table_updates() {
+        let (kept, dropped) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let mut fee_splits = vec![
+            FeeSplit {
+                recipient: kept,
+                bps: 250,
+                ..FeeSplit::default()
+            },
+            FeeSplit {
+                recipient: dropped,
+                bps: 125,
+                ..FeeSplit::default()
+            },
+        ];
+
+        // 2.5% and 1.25% of each sale, each rounded down on its own.
+        assert_eq!(accrue_fees(&mut fee_splits, 999), Some(24 + 12));
+        assert_eq!(accrue_fees(&mut fee_splits, 1000), Some(25 + 12));
+        assert_eq!(accrue_fees(&mut fee_splits, 39), Some(0));
+        assert_eq!(fee_splits[0].accrued, 49);
+        assert_eq!(fee_splits[1].accrued, 24);
+
+        // The kept recipient keeps its balance at the new rate; the dropped one's is released.
+        let added = Pubkey::new_unique();
+        let (splits, released) = carry_over_fees(
+            &fee_splits,
+            &[
+                FeeSplitInfo {
+                    recipient: added,
+                    bps: 100,
+                },
+                FeeSplitInfo {
+                    recipient: kept,
+                    bps: 300,
+                },
+            ],
+        )
+        .unwrap();
+        assert_eq!(released, 24);
+        assert_eq!(
+            splits
+                .iter()
+                .map(|split| (split.recipient, split.bps, split.accrued))
+                .collect::<Vec<_>>(),
+            vec![(added, 100, 0), (kept, 300, 49)]
+        );
+
+        fee_splits[0].accrued = u64::MAX;
+        assert_eq!(accrue_fees(&mut fee_splits, 1000), None);
+    }
+}
@@ -1,18 +1,34 @@
 use anchor_lang::prelude::*;
-use anchor_lang::solana_program::hash::hash;
+use anchor_lang::solana_program::hash::{hash, hashv};
+use anchor_spl::associated_token::{self, AssociatedToken};
 use anchor_spl::token::{self, Token, TokenAccount, Transfer};
 use solana_program::clock::Clock;
 
 declare_id!("4Vi9n94NDfjgd4d4ETVKKfsPYym1ugenokxNt6TtyGth");
 
-pub const SECONDS_PER_MINUTE: i64 = 60;
-pub const MINUTES_PER_HOUR: i64 = 60;
-pub const DRAW_WINDOW_MINUTES: i64 = 5; // Lottery window (minutes)
-pub const MIN_DRAW_INTERVAL: i64 = 55 * SECONDS_PER_MINUTE; // Minimum draw interval
-pub const LOCK_DURATION: i64 = 5 * SECONDS_PER_MINUTE; // 5 minute lock period
 pub const NUMBERS_COUNT: usize = 3; // 3D lottery requires 3 numbers
-pub const MAX_NUMBER: u8 = 33; // maximum number
-pub const MIN_NUMBER: u8 = 1; // minimum number
+pub const MAX_NUMBER: u8 = 9; // maximum digit
+pub const MIN_NUMBER: u8 = 0; // minimum digit
+pub const MAX_SUM: usize = 27; // 9 + 9 + 9
+pub const TOTAL_COMBINATIONS: u64 = 1000; // 000-999
+pub const MAX_LINES_PER_TICKET: usize = 32;
+pub const DRAW_DELAY_SLOTS: u64 = 2; // draw randomness comes from a slot hash this far past lock_draw
+pub const BPS_DENOMINATOR: u64 = 10000;
+pub const MAX_FEE_SPLITS: usize = 4;
+pub const DEFAULT_REVENUE_SPLIT: RevenueSplit = RevenueSplit {
+    prize_bps: BPS_DENOMINATOR as u16,
+    reserve_bps: 0,
+    house_bps: 0,
+};
+pub const DEFAULT_PAYTABLE: Paytable = Paytable {
+    straight: 520,
+    group3: 173,
+    group6: 86,
+    sum: [
+        520, 173, 86, 52, 34, 24, 18, 14, 11, 9, 8, 7, 7, 7, 7, 7, 7, 8, 9, 11, 14, 18, 24, 34, 52,
+        86, 173, 520,
+    ],
+}; // multiplier of the stake per bet type
 
 #[program]
 pub mod lottery_3d_contract {
@@ -20,11 +36,16 @@ pub mod lottery_3d_contract {
 
     pub fn initialize(
         ctx: Context<Initialize>,
+        lottery_id: u64,
         min_purchase_amount: u32,
         token_mint: Pubkey,
+        schedule: DrawSchedule,
     ) -> Result<()> {
+        require!(schedule.is_valid(), LotteryError::InvalidSchedule);
+
         let lottery = &mut ctx.accounts.lottery;
         lottery.authority = ctx.accounts.authority.key();
+        lottery.lottery_id = lottery_id;
         lottery.token_account = ctx.accounts.token_account.key();
         lottery.token_mint = token_mint;
         lottery.last_draw_time = 0;
@@ -32,9 +53,26 @@ pub mod lottery_3d_contract {
         lottery.min_purchase_amount = min_purchase_amount;
         lottery.last_draw_numbers = [0; NUMBERS_COUNT];
         lottery.last_prize_amount = 0;
+        lottery.current_round = 0;
+        lottery.ticket_count = 0;
+        lottery.paytable = DEFAULT_PAYTABLE;
+        lottery.schedule = schedule;
+        lottery.revenue_split = DEFAULT_REVENUE_SPLIT;
+        lottery.reserve_pool = 0;
+        lottery.house_revenue = 0;
+        lottery.fee_splits = Vec::new();
+        lottery.unsettled_tickets = 0;
+        lottery.open_prize_pool = 0;
+
+        let round = &mut ctx.accounts.round;
+        round.lottery = lottery.key();
+        round.round_id = 0;
+        round.start_time = Clock::get()?.unix_timestamp;
+        round.paytable = lottery.paytable;
 
         emit!(LotteryInitialized {
             authority: lottery.authority,
+            lottery_id,
             token_mint,
             min_purchase_amount,
         });
@@ -44,37 +82,43 @@ pub mod lottery_3d_contract {
 
     pub fn buy_ticket(
         ctx: Context<BuyTicket>,
+        bet_type: BetType,
         numbers: [u8; NUMBERS_COUNT],
         amount: u64,
+        quick_pick: bool,
     ) -> Result<()> {
         let clock = Clock::get()?;
         let current_timestamp = clock.unix_timestamp;
         let lottery = &mut ctx.accounts.lottery;
 
-        require!(
-            !lottery.is_in_draw_window(current_timestamp),
-            LotteryError::DrawWindowActive
-        );
-
         require!(
             ctx.accounts.lottery_token_account.mint == lottery.token_mint
                 && ctx.accounts.buyer_token_account.mint == lottery.token_mint,
             LotteryError::InvalidTokenMint
         );
 
-        if lottery.is_locked {
-            let time_since_last_draw = current_timestamp - lottery.last_draw_time;
-            require!(time_since_last_draw > LOCK_DURATION, LotteryError::Locked);
-            lottery.is_locked = false;
-        }
+        lottery.open_sales(current_timestamp)?;
 
         require!(
             amount >= lottery.min_purchase_amount as u64,
             LotteryError::InsufficientAmount
         );
 
+        let numbers = if quick_pick {
+            let seed = quick_pick_seed(
+                &lottery.key(),
+                lottery.ticket_count,
+                &ctx.accounts.buyer.key(),
+                &clock,
+                0,
+            );
+            quick_pick_numbers(bet_type, &seed).ok_or(LotteryError::InvalidTicketNumbers)?
+        } else {
+            numbers
+        };
+
         require!(
-            validate_ticket_numbers(&numbers),
+            validate_ticket_numbers(bet_type, &numbers),
             LotteryError::InvalidTicketNumbers
         );
 
@@ -88,17 +132,183 @@ pub mod lottery_3d_contract {
         );
         token::transfer(transfer_ctx, amount)?;
 
+        let ticket = &mut ctx.accounts.ticket;
+        ticket.buyer = ctx.accounts.buyer.key();
+        ticket.lines = vec![TicketLine {
+            bet_type,
+            numbers,
+            amount,
+        }];
+        enter_ticket(
+            lottery,
+            &mut ctx.accounts.round,
+            ticket,
+            amount,
+            current_timestamp,
+        )?;
+
         emit!(TicketPurchased {
-            buyer: ctx.accounts.buyer.key(),
+            buyer: ticket.buyer,
+            round_id: ticket.round_id,
+            ticket_id: ticket.ticket_id,
+            bet_type,
             numbers,
             amount,
+            quick_pick,
+            timestamp: current_timestamp,
+        });
+
+        Ok(())
+    }
+
+    pub fn buy_tickets(ctx: Context<BuyTickets>, lines: Vec<LineInfo>) -> Result<()> {
+        let clock = Clock::get()?;
+        let current_timestamp = clock.unix_timestamp;
+        let lottery = &mut ctx.accounts.lottery;
+
+        require!(
+            ctx.accounts.lottery_token_account.mint == lottery.token_mint
+                && ctx.accounts.buyer_token_account.mint == lottery.token_mint,
+            LotteryError::InvalidTokenMint
+        );
+
+        lottery.open_sales(current_timestamp)?;
+
+        require!(
+            !lines.is_empty() && lines.len() <= MAX_LINES_PER_TICKET,
+            LotteryError::InvalidLineCount
+        );
+
+        let mut lines = lines;
+        let mut ticket_lines = Vec::with_capacity(lines.len());
+        let mut total_amount: u64 = 0;
+        for (index, line) in lines.iter_mut().enumerate() {
+            require!(line.multiplier > 0, LotteryError::InsufficientAmount);
+            if line.quick_pick {
+                let seed = quick_pick_seed(
+                    &lottery.key(),
+                    lottery.ticket_count,
+                    &ctx.accounts.buyer.key(),
+                    &clock,
+                    index,
+                );
+                line.numbers = quick_pick_numbers(line.bet_type, &seed)
+                    .ok_or(LotteryError::InvalidTicketNumbers)?;
+            }
+            require!(
+                validate_ticket_numbers(line.bet_type, &line.numbers),
+                LotteryError::InvalidTicketNumbers
+            );
+
+            let amount = (lottery.min_purchase_amount as u64)
+                .checked_mul(line.multiplier as u64)
+                .ok_or(LotteryError::ArithmeticError)?;
+            total_amount = total_amount
+                .checked_add(amount)
+                .ok_or(LotteryError::ArithmeticError)?;
+            ticket_lines.push(TicketLine {
+                bet_type: line.bet_type,
+                numbers: line.numbers,
+                amount,
+            });
+        }
+
+        let transfer_ctx = CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.buyer_token_account.to_account_info(),
+                to: ctx.accounts.lottery_token_account.to_account_info(),
+                authority: ctx.accounts.buyer.to_account_info(),
+            },
+        );
+        token::transfer(transfer_ctx, total_amount)?;
+
+        let ticket = &mut ctx.accounts.ticket;
+        ticket.buyer = ctx.accounts.buyer.key();
+        ticket.lines = ticket_lines;
+        enter_ticket(
+            lottery,
+            &mut ctx.accounts.round,
+            ticket,
+            total_amount,
+            current_timestamp,
+        )?;
+
+        emit!(TicketLinesPurchased {
+            buyer: ticket.buyer,
+            round_id: ticket.round_id,
+            ticket_id: ticket.ticket_id,
+            lines,
+            amount: total_amount,
             timestamp: current_timestamp,
         });
 
         Ok(())
     }
 
-    pub fn draw(ctx: Context<Draw>, uuid: String) -> Result<()> {
+    pub fn commit_draw(ctx: Context<CommitDraw>, commitment: [u8; 32]) -> Result<()> {
+        let lottery = &ctx.accounts.lottery;
+        let round = &mut ctx.accounts.round;
+
+        require!(
+            !lottery.is_in_draw_window(Clock::get()?.unix_timestamp),
+            LotteryError::DrawWindowActive
+        );
+        require!(!round.is_drawn, LotteryError::AlreadyDrawn);
+        require!(
+            round.commitment == [0u8; 32],
+            LotteryError::AlreadyCommitted
+        );
+        require!(commitment != [0u8; 32], LotteryError::InvalidCommitment);
+
+        round.commitment = commitment;
+        round.commit_slot = Clock::get()?.slot;
+
+        emit!(DrawCommitted {
+            round_id: round.round_id,
+            commitment,
+            commit_slot: round.commit_slot,
+        });
+
+        Ok(())
+    }
+
+    pub fn lock_draw(ctx: Context<LockDraw>) -> Result<()> {
+        let clock = Clock::get()?;
+        let lottery = &ctx.accounts.lottery;
+
+        require!(
+            lottery.is_in_draw_window(clock.unix_timestamp),
+            LotteryError::InvalidDrawTime
+        );
+        require!(
+            lottery.can_draw(clock.unix_timestamp),
+            LotteryError::DrawTooEarly
+        );
+        require!(!lottery.is_locked, LotteryError::AlreadyDrawn);
+
+        let round = &mut ctx.accounts.round;
+        require!(
+            round.commitment != [0u8; 32],
+            LotteryError::DrawNotCommitted
+        );
+
+        require!(round.target_slot == 0, LotteryError::DrawAlreadyLocked);
+
+        round.target_slot = clock
+            .slot
+            .checked_add(DRAW_DELAY_SLOTS)
+            .ok_or(LotteryError::ArithmeticError)?;
+
+        emit!(DrawLocked {
+            round_id: round.round_id,
+            target_slot: round.target_slot,
+        });
+
+        Ok(())
+    }
+
+    pub fn draw(ctx: Context<Draw>, secret: [u8; 32]) -> Result<()> {
         let clock = Clock::get()?;
         let current_timestamp = clock.unix_timestamp;
         let lottery = &mut ctx.accounts.lottery;
@@ -115,13 +325,23 @@ pub mod lottery_3d_contract {
 
         require!(!lottery.is_locked, LotteryError::AlreadyDrawn);
 
-        let recent_blockhashes = ctx.accounts.recent_blockhashes.try_borrow_data()?;
-        let random_value = generate_vrf_random_number(
-            current_timestamp,
-            &recent_blockhashes,
-            &lottery.to_account_info(),
-            &uuid,
-        )?;
+        let round = &mut ctx.accounts.round;
+        require!(round.target_slot != 0, LotteryError::DrawNotLocked);
+        require!(clock.slot > round.target_slot, LotteryError::RevealTooEarly);
+
+        require!(
+            hash(&secret).to_bytes() == round.commitment,
+            LotteryError::InvalidReveal
+        );
+
+        let (slot, slot_hash) = find_draw_slot_hash(
+            &ctx.accounts.slot_hashes.try_borrow_data()?,
+            round.target_slot,
+        )
+        .ok_or(LotteryError::DrawExpired)?;
+
+        let random_value =
+            generate_draw_random_number(&secret, &slot_hash, &lottery.key(), round.round_id);
 
         let draw_numbers = convert_random_to_3d_numbers(&random_value);
 
@@ -129,7 +349,33 @@ pub mod lottery_3d_contract {
         lottery.is_locked = true;
         lottery.last_draw_numbers = draw_numbers;
 
+        round.is_drawn = true;
+        round.draw_time = current_timestamp;
+        round.draw_numbers = draw_numbers;
+        round.draw_slot = slot;
+        round.slot_hash = slot_hash;
+        round.secret = secret;
+
+        // Every ticket of a drawn round is an open liability until it is settled.
+        lottery.unsettled_tickets = lottery
+            .unsettled_tickets
+            .checked_add(round.ticket_count)
+            .ok_or(LotteryError::ArithmeticError)?;
+
+        lottery.current_round = lottery
+            .current_round
+            .checked_add(1)
+            .ok_or(LotteryError::ArithmeticError)?;
+
+        let next_round = &mut ctx.accounts.next_round;
+        next_round.lottery = lottery.key();
+        next_round.round_id = lottery.current_round;
+        next_round.start_time = current_timestamp;
+        // Tickets are sold against the paytable their round opened with.
+        next_round.paytable = lottery.paytable;
+
         emit!(DrawResult {
+            round_id: round.round_id,
             numbers: draw_numbers,
             draw_time: lottery.last_draw_time,
         });
@@ -137,23 +383,370 @@ pub mod lottery_3d_contract {
         Ok(())
     }
 
-    pub fn update_prize_amount(ctx: Context<UpdatePrize>, prize_amount: u64) -> Result<()> {
-        let lottery = &mut ctx.accounts.lottery;
+    pub fn void_round(ctx: Context<Draw>) -> Result<()> {
         let clock = Clock::get()?;
-        let current_timestamp = clock.unix_timestamp;
+        let lottery = &mut ctx.accounts.lottery;
 
+        // A locked round whose slot hash aged out unrevealed can no longer be
+        // drawn, so it is voided and its tickets are refunded in full.
+        let round = &mut ctx.accounts.round;
+        require!(round.target_slot != 0, LotteryError::DrawNotLocked);
         require!(
-            lottery.is_locked && current_timestamp - lottery.last_draw_time <= LOCK_DURATION,
-            LotteryError::PrizeUpdateWindowClosed
+            clock.slot > round.target_slot + solana_program::slot_hashes::MAX_ENTRIES as u64
+                && find_draw_slot_hash(
+                    &ctx.accounts.slot_hashes.try_borrow_data()?,
+                    round.target_slot
+                )
+                .is_none(),
+            LotteryError::RevealStillPossible
         );
+        round.is_voided = true;
 
-        require!(prize_amount > 0, LotteryError::InvalidPrizeAmount);
+        // Refunds are settled ticket by ticket, like prizes.
+        lottery.unsettled_tickets = lottery
+            .unsettled_tickets
+            .checked_add(round.ticket_count)
+            .ok_or(LotteryError::ArithmeticError)?;
 
-        lottery.last_prize_amount += prize_amount;
+        lottery.current_round = lottery
+            .current_round
+            .checked_add(1)
+            .ok_or(LotteryError::ArithmeticError)?;
 
-        emit!(PrizeAmountUpdated {
-            amount: prize_amount,
-            draw_time: lottery.last_draw_time,
+        let next_round = &mut ctx.accounts.next_round;
+        next_round.lottery = lottery.key();
+        next_round.round_id = lottery.current_round;
+        next_round.start_time = clock.unix_timestamp;
+        next_round.paytable = lottery.paytable;
+
+        emit!(RoundVoided {
+            round_id: round.round_id,
+            ticket_count: round.ticket_count,
+            refund_amount: round.total_amount,
+        });
+
+        Ok(())
+    }
+
+    pub fn settle_ticket(ctx: Context<SettleTicket>) -> Result<()> {
+        let round = &mut ctx.accounts.round;
+        let ticket = &mut ctx.accounts.ticket;
+
+        require!(
+            round.is_drawn || round.is_voided,
+            LotteryError::RoundNotDrawn
+        );
+        require!(!ticket.is_settled, LotteryError::TicketAlreadySettled);
+
+        // A voided round refunds each ticket what it paid.
+        let prize_amount = if round.is_voided {
+            ticket.amount
+        } else {
+            let mut prize_amount: u64 = 0;
+            for line in ticket.lines.iter() {
+                let multiplier =
+                    round
+                        .paytable
+                        .multiplier(line.bet_type, &line.numbers, &round.draw_numbers);
+                let line_prize = line
+                    .amount
+                    .checked_mul(multiplier as u64)
+                    .ok_or(LotteryError::ArithmeticError)?;
+                prize_amount = prize_amount
+                    .checked_add(line_prize)
+                    .ok_or(LotteryError::ArithmeticError)?;
+            }
+            prize_amount
+        };
+
+        ticket.is_settled = true;
+        ticket.prize_amount = prize_amount;
+
+        // Prizes beyond what the round's prize share still holds come out of
+        // the reserve, then out of house revenue.
+        let lottery = &mut ctx.accounts.lottery;
+        let (from_pool, from_reserve, from_house) = fund_prize(
+            prize_amount,
+            round.prize_pool.saturating_sub(round.total_prize),
+            lottery.reserve_pool,
+            lottery.house_revenue,
+        );
+        lottery.open_prize_pool = lottery
+            .open_prize_pool
+            .checked_sub(from_pool)
+            .ok_or(LotteryError::ArithmeticError)?;
+        lottery.reserve_pool = lottery
+            .reserve_pool
+            .checked_sub(from_reserve)
+            .ok_or(LotteryError::ArithmeticError)?;
+        lottery.house_revenue = lottery
+            .house_revenue
+            .checked_sub(from_house)
+            .ok_or(LotteryError::ArithmeticError)?;
+
+        round.settled_count = round
+            .settled_count
+            .checked_add(1)
+            .ok_or(LotteryError::ArithmeticError)?;
+        round.total_prize = round
+            .total_prize
+            .checked_add(prize_amount)
+            .ok_or(LotteryError::ArithmeticError)?;
+
+        lottery.last_prize_amount = lottery
+            .last_prize_amount
+            .checked_add(prize_amount)
+            .ok_or(LotteryError::ArithmeticError)?;
+        lottery.unsettled_tickets = lottery
+            .unsettled_tickets
+            .checked_sub(1)
+            .ok_or(LotteryError::ArithmeticError)?;
+
+        // A fully settled round hands whatever its prize share did not pay out to the reserve.
+        if round.settled_count == round.ticket_count {
+            let surplus = round.prize_pool.saturating_sub(round.total_prize);
+            lottery.open_prize_pool = lottery
+                .open_prize_pool
+                .checked_sub(surplus)
+                .ok_or(LotteryError::ArithmeticError)?;
+            lottery.reserve_pool = lottery
+                .reserve_pool
+                .checked_add(surplus)
+                .ok_or(LotteryError::ArithmeticError)?;
+        }
+
+        emit!(TicketSettled {
+            round_id: round.round_id,
+            ticket_id: ticket.ticket_id,
+            buyer: ticket.buyer,
+            prize_amount,
+        });
+
+        Ok(())
+    }
+
+    pub fn update_paytable(ctx: Context<UpdateConfig>, paytable: Paytable) -> Result<()> {
+        require!(paytable.is_valid(), LotteryError::InvalidPaytable);
+        require!(
+            ctx.accounts.lottery.revenue_split.prize_bps as u64 >= paytable.max_rtp_bps(),
+            LotteryError::PrizeShareTooLow
+        );
+
+        // Applies from the next round; the open round keeps the paytable it sold under.
+        ctx.accounts.lottery.paytable = paytable;
+
+        emit!(PaytableUpdated { paytable });
+
+        Ok(())
+    }
+
+    pub fn update_schedule(ctx: Context<UpdateConfig>, schedule: DrawSchedule) -> Result<()> {
+        require!(schedule.is_valid(), LotteryError::InvalidSchedule);
+
+        let lottery = &mut ctx.accounts.lottery;
+        require!(
+            !lottery.is_in_draw_window(Clock::get()?.unix_timestamp),
+            LotteryError::DrawWindowActive
+        );
+
+        lottery.schedule = schedule;
+
+        emit!(ScheduleUpdated {
+            draw_interval: schedule.draw_interval,
+            draw_offset: schedule.draw_offset,
+            draw_window: schedule.draw_window,
+            lock_duration: schedule.lock_duration,
+        });
+
+        Ok(())
+    }
+
+    pub fn update_revenue_split(
+        ctx: Context<UpdateConfig>,
+        revenue_split: RevenueSplit,
+    ) -> Result<()> {
+        let lottery = &mut ctx.accounts.lottery;
+        require!(revenue_split.is_valid(), LotteryError::InvalidRevenueSplit);
+        require!(
+            revenue_split.covers_fees(lottery.fee_bps()),
+            LotteryError::FeeSplitTooHigh
+        );
+        require!(
+            revenue_split.prize_bps as u64 >= lottery.paytable.max_rtp_bps(),
+            LotteryError::PrizeShareTooLow
+        );
+
+        lottery.revenue_split = revenue_split;
+
+        emit!(RevenueSplitUpdated {
+            prize_bps: revenue_split.prize_bps,
+            reserve_bps: revenue_split.reserve_bps,
+            house_bps: revenue_split.house_bps,
+        });
+
+        Ok(())
+    }
+
+    pub fn withdraw_revenue(ctx: Context<WithdrawRevenue>, amount: u64) -> Result<()> {
+        let lottery = &ctx.accounts.lottery;
+        require!(
+            amount > 0 && amount <= lottery.house_revenue,
+            LotteryError::InsufficientRevenue
+        );
+        require!(
+            lottery.unsettled_tickets == 0,
+            LotteryError::TicketsUnsettled
+        );
+
+        // Keep enough behind for credited prizes, prize shares not yet paid
+        // out, the reserve and unswept fees.
+        let committed = lottery
+            .last_prize_amount
+            .checked_add(lottery.open_prize_pool)
+            .and_then(|total| total.checked_add(lottery.reserve_pool))
+            .and_then(|total| total.checked_add(lottery.accrued_fees()))
+            .ok_or(LotteryError::ArithmeticError)?;
+        let remaining_balance = ctx
+            .accounts
+            .lottery_token_account
+            .amount
+            .checked_sub(amount)
+            .ok_or(LotteryError::LiabilitiesUncovered)?;
+        require!(
+            remaining_balance >= committed,
+            LotteryError::LiabilitiesUncovered
+        );
+
+        let auth_key = lottery.authority;
+        let lottery_id = lottery.lottery_id.to_le_bytes();
+        let signer_seeds: &[&[&[u8]]] = &[&[
+            b"lottery",
+            auth_key.as_ref(),
+            &lottery_id,
+            &[ctx.bumps.lottery],
+        ]];
+
+        token::transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                token::Transfer {
+                    from: ctx.accounts.lottery_token_account.to_account_info(),
+                    to: ctx.accounts.authority_token_account.to_account_info(),
+                    authority: ctx.accounts.lottery.to_account_info(),
+                },
+                signer_seeds,
+            ),
+            amount,
+        )?;
+
+        let lottery = &mut ctx.accounts.lottery;
+        lottery.house_revenue = lottery
+            .house_revenue
+            .checked_sub(amount)
+            .ok_or(LotteryError::ArithmeticError)?;
+
+        emit!(RevenueWithdrawn {
+            amount,
+            house_revenue: lottery.house_revenue,
+            recipient: ctx.accounts.authority_token_account.key(),
+        });
+
+        Ok(())
+    }
+
+    pub fn update_fee_splits(
+        ctx: Context<UpdateConfig>,
+        fee_splits: Vec<FeeSplitInfo>,
+    ) -> Result<()> {
+        let lottery = &mut ctx.accounts.lottery;
+        require!(
+            fee_splits.len() <= MAX_FEE_SPLITS,
+            LotteryError::TooManyFeeSplits
+        );
+
+        let mut recipients = std::collections::HashSet::new();
+        let mut fee_bps: u64 = 0;
+        for split in fee_splits.iter() {
+            require!(
+                split.bps > 0 && recipients.insert(split.recipient),
+                LotteryError::InvalidFeeSplit
+            );
+            fee_bps += split.bps as u64;
+        }
+        require!(
+            lottery.revenue_split.covers_fees(fee_bps),
+            LotteryError::FeeSplitTooHigh
+        );
+
+        let released_fees = lottery
+            .replace_fee_splits(&fee_splits)
+            .ok_or(LotteryError::ArithmeticError)?;
+
+        emit!(FeeSplitsUpdated {
+            fee_splits,
+            released_fees,
+        });
+
+        Ok(())
+    }
+
+    pub fn sweep_fees<'info>(ctx: Context<'_, '_, '_, 'info, SweepFees<'info>>) -> Result<()> {
+        let fee_splits = ctx.accounts.lottery.fee_splits.clone();
+
+        // Recipient token accounts are passed in fee split table order.
+        require!(
+            ctx.remaining_accounts.len() == fee_splits.len(),
+            LotteryError::InvalidFeeRecipient
+        );
+
+        let auth_key = ctx.accounts.lottery.authority;
+        let lottery_id = ctx.accounts.lottery.lottery_id.to_le_bytes();
+        let signer_seeds: &[&[&[u8]]] = &[&[
+            b"lottery",
+            auth_key.as_ref(),
+            &lottery_id,
+            &[ctx.bumps.lottery],
+        ]];
+
+        for (split, recipient_account) in fee_splits.iter().zip(ctx.remaining_accounts.iter()) {
+            require!(
+                recipient_account.key() == split.recipient && recipient_account.owner == &token::ID,
+                LotteryError::InvalidFeeRecipient
+            );
+            if split.accrued == 0 {
+                continue;
+            }
+
+            let recipient_token_account =
+                TokenAccount::try_deserialize(&mut &recipient_account.try_borrow_data()?[..])
+                    .map_err(|_| LotteryError::InvalidFeeRecipient)?;
+            require!(
+                recipient_token_account.mint == ctx.accounts.lottery.token_mint,
+                LotteryError::InvalidTokenMint
+            );
+
+            token::transfer(
+                CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info(),
+                    token::Transfer {
+                        from: ctx.accounts.lottery_token_account.to_account_info(),
+                        to: recipient_account.clone(),
+                        authority: ctx.accounts.lottery.to_account_info(),
+                    },
+                    signer_seeds,
+                ),
+                split.accrued,
+            )?;
+        }
+
+        let lottery = &mut ctx.accounts.lottery;
+        for split in lottery.fee_splits.iter_mut() {
+            split.accrued = 0;
+        }
+
+        emit!(FeesSwept {
+            recipients: fee_splits.iter().map(|split| split.recipient).collect(),
+            amounts: fee_splits.iter().map(|split| split.accrued).collect(),
         });
 
         Ok(())
@@ -196,6 +789,7 @@ pub mod lottery_3d_contract {
         ctx: Context<'_, '_, '_, 'info, TransferToken<'info>>,
         transfers: Vec<TransferInfo>,
         total_amount: u64,
+        create_accounts: bool,
     ) -> Result<()> {
         require!(total_amount > 0, LotteryError::InsufficientPrizeAmount);
         require!(
@@ -203,16 +797,109 @@ pub mod lottery_3d_contract {
             LotteryError::InsufficientPrizeAmount
         );
 
-        let auth_key = ctx.accounts.authority.key();
+        // Each payout passes its winning ticket from `round` and the recipient token account,
+        // plus the recipient wallet when missing token accounts should be created.
+        let accounts_per_transfer = if create_accounts { 3 } else { 2 };
+        require!(
+            ctx.remaining_accounts.len() == transfers.len() * accounts_per_transfer,
+            LotteryError::RecipientCountMismatch
+        );
 
-        for (i, transfer) in transfers.iter().enumerate() {
-            let recipient_account = ctx
-                .remaining_accounts
-                .get(i)
-                .ok_or(LotteryError::InvalidTokenMint)?;
+        let mut transfer_sum: u64 = 0;
+        let mut recipients = std::collections::HashSet::new();
+        let mut ticket_ids = std::collections::HashSet::new();
+        for transfer in transfers.iter() {
+            require!(transfer.amount > 0, LotteryError::InvalidPrizeAmount);
+            require!(
+                recipients.insert(transfer.recipient),
+                LotteryError::DuplicateRecipient
+            );
+            require!(
+                ticket_ids.insert(transfer.ticket_id),
+                LotteryError::DuplicatePayoutTicket
+            );
+            transfer_sum = transfer_sum
+                .checked_add(transfer.amount)
+                .ok_or(LotteryError::ArithmeticError)?;
+        }
+        require!(
+            transfer_sum == total_amount,
+            LotteryError::TransferTotalMismatch
+        );
 
-            let signer_seeds: &[&[&[u8]]] =
-                &[&[b"lottery", auth_key.as_ref(), &[ctx.bumps.lottery]]];
+        let lottery_key = ctx.accounts.lottery.key();
+        let auth_key = ctx.accounts.authority.key();
+        let lottery_id = ctx.accounts.lottery.lottery_id.to_le_bytes();
+        let mut remaining_prize = ctx.accounts.lottery.last_prize_amount;
+        let mut paid_amount: u64 = 0;
+        let mut paid_count: u8 = 0;
+        for (transfer, accounts) in transfers
+            .iter()
+            .zip(ctx.remaining_accounts.chunks(accounts_per_transfer))
+        {
+            let (ticket_account, recipient_account) = (&accounts[0], &accounts[1]);
+
+            require!(
+                ticket_account.owner == &crate::ID,
+                LotteryError::InvalidPayoutTicket
+            );
+            let mut ticket = Ticket::try_deserialize(&mut &ticket_account.try_borrow_data()?[..])
+                .map_err(|_| LotteryError::InvalidPayoutTicket)?;
+
+            // Already-paid tickets are skipped so a failed batch can be resubmitted as is.
+            if !check_payout(&lottery_key, &ctx.accounts.round, &ticket, transfer)? {
+                emit!(PayoutSkipped {
+                    ticket_id: ticket.ticket_id,
+                    recipient: transfer.recipient,
+                    amount: transfer.amount,
+                });
+                continue;
+            }
+
+            if create_accounts {
+                let recipient_wallet = &accounts[2];
+                check_recipient_ata(
+                    &recipient_wallet.key(),
+                    &recipient_account.key(),
+                    &transfer.recipient,
+                    &ctx.accounts.lottery.token_mint,
+                )?;
+
+                associated_token::create_idempotent(CpiContext::new(
+                    ctx.accounts.associated_token_program.to_account_info(),
+                    associated_token::Create {
+                        payer: ctx.accounts.authority.to_account_info(),
+                        associated_token: recipient_account.clone(),
+                        authority: recipient_wallet.clone(),
+                        mint: ctx.accounts.mint.to_account_info(),
+                        system_program: ctx.accounts.system_program.to_account_info(),
+                        token_program: ctx.accounts.token_program.to_account_info(),
+                    },
+                ))?;
+            }
+
+            require!(
+                recipient_account.owner == &token::ID,
+                LotteryError::InvalidRecipientAccount
+            );
+            let recipient_token_account =
+                TokenAccount::try_deserialize(&mut &recipient_account.try_borrow_data()?[..])
+                    .map_err(|_| LotteryError::InvalidRecipientAccount)?;
+            require!(
+                recipient_token_account.mint == ctx.accounts.lottery.token_mint,
+                LotteryError::InvalidTokenMint
+            );
+            require!(
+                recipient_token_account.owner == transfer.recipient,
+                LotteryError::RecipientOwnerMismatch
+            );
+
+            let signer_seeds: &[&[&[u8]]] = &[&[
+                b"lottery",
+                auth_key.as_ref(),
+                &lottery_id,
+                &[ctx.bumps.lottery],
+            ]];
 
             token::transfer(
                 CpiContext::new_with_signer(
@@ -227,22 +914,35 @@ pub mod lottery_3d_contract {
                 transfer.amount,
             )?;
 
+            ticket.is_paid = true;
+            ticket.prize_amount = transfer.amount;
+            ticket.try_serialize(&mut &mut ticket_account.try_borrow_mut_data()?[..])?;
+
+            paid_amount += transfer.amount;
+            paid_count += 1;
+            remaining_prize -= transfer.amount;
             emit!(TokenDrawTransfer {
                 amount: transfer.amount,
-                recipient: transfer.recipient,
-                remaining_prize: ctx.accounts.lottery.last_prize_amount - transfer.amount,
+                recipient: recipient_token_account.owner,
+                remaining_prize,
             });
         }
 
+        let round = &mut ctx.accounts.round;
+        round.total_paid = round
+            .total_paid
+            .checked_add(paid_amount)
+            .ok_or(LotteryError::ArithmeticError)?;
+
         let lottery = &mut ctx.accounts.lottery;
         lottery.last_prize_amount = lottery
             .last_prize_amount
-            .checked_sub(total_amount)
+            .checked_sub(paid_amount)
             .ok_or(LotteryError::ArithmeticError)?;
 
         emit!(BatchTransferCompleted {
-            total_amount,
-            transfer_count: transfers.len() as u8,
+            total_amount: paid_amount,
+            transfer_count: paid_count,
             remaining_prize: lottery.last_prize_amount,
             timestamp: Clock::get()?.unix_timestamp,
         });
@@ -255,6 +955,7 @@ pub mod lottery_3d_contract {
 #[derive(Default)]
 pub struct LotteryState {
     pub authority: Pubkey,
+    pub lottery_id: u64,
     pub token_account: Pubkey,
     pub token_mint: Pubkey,
     pub last_draw_time: i64,
@@ -262,31 +963,138 @@ pub struct LotteryState {
     pub min_purchase_amount: u32,
     pub last_draw_numbers: [u8; NUMBERS_COUNT],
     pub last_prize_amount: u64,
+    pub current_round: u64,
+    pub ticket_count: u64,
+    pub paytable: Paytable,
+    pub schedule: DrawSchedule,
+    pub revenue_split: RevenueSplit,
+    pub reserve_pool: u64,
+    pub house_revenue: u64,
+    pub fee_splits: Vec<FeeSplit>,
+    pub unsettled_tickets: u64,
+    pub open_prize_pool: u64,
 }
 
-#[derive(Accounts)]
-pub struct Initialize<'info> {
-    #[account(
-        init,
-        payer = authority,
-        space = 8 + 32 + 32 + 32 + 8 + 1 + 4 + 3 + 8 + 8 + 8 + 8,
-        seeds = [b"lottery", authority.key().as_ref()],
-        bump
-    )]
-    pub lottery: Account<'info, LotteryState>,
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default)]
+pub struct RevenueSplit {
+    pub prize_bps: u16,
+    pub reserve_bps: u16,
+    pub house_bps: u16,
+}
 
-    #[account(
-        init,
-        payer = authority,
-        seeds = [b"token_account", authority.key().as_ref()],
-        bump,
-        token::mint = token_mint,
-        token::authority = lottery
-    )]
-    pub token_account: Box<Account<'info, TokenAccount>>,
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default)]
+pub struct FeeSplit {
+    pub recipient: Pubkey,
+    pub bps: u16,
+    pub accrued: u64,
+}
 
-    /// CHECK: Token mint account
-    pub token_mint: AccountInfo<'info>,
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BetType {
+    #[default]
+    Straight,
+    Group3,
+    Group6,
+    Sum,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default)]
+pub struct Paytable {
+    pub straight: u32,
+    pub group3: u32,
+    pub group6: u32,
+    pub sum: [u32; MAX_SUM + 1],
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default)]
+pub struct DrawSchedule {
+    pub draw_interval: i64,
+    pub draw_offset: i64,
+    pub draw_window: i64,
+    pub lock_duration: i64,
+}
+
+#[account]
+#[derive(Default)]
+pub struct Round {
+    pub lottery: Pubkey,
+    pub round_id: u64,
+    pub start_time: i64,
+    pub ticket_count: u64,
+    pub total_amount: u64,
+    pub is_drawn: bool,
+    pub draw_time: i64,
+    pub draw_numbers: [u8; NUMBERS_COUNT],
+    pub paytable: Paytable,
+    pub settled_count: u64,
+    pub total_prize: u64,
+    pub commitment: [u8; 32],
+    pub commit_slot: u64,
+    pub draw_slot: u64,
+    pub slot_hash: [u8; 32],
+    pub secret: [u8; 32],
+    pub target_slot: u64,
+    pub is_voided: bool,
+    pub total_paid: u64,
+    pub prize_pool: u64,
+}
+
+#[account]
+#[derive(Default)]
+pub struct Ticket {
+    pub lottery: Pubkey,
+    pub round_id: u64,
+    pub ticket_id: u64,
+    pub buyer: Pubkey,
+    pub lines: Vec<TicketLine>,
+    pub amount: u64,
+    pub purchase_time: i64,
+    pub is_settled: bool,
+    pub prize_amount: u64,
+    pub is_paid: bool,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Default)]
+pub struct TicketLine {
+    pub bet_type: BetType,
+    pub numbers: [u8; NUMBERS_COUNT],
+    pub amount: u64,
+}
+
+#[derive(Accounts)]
+#[instruction(lottery_id: u64)]
+pub struct Initialize<'info> {
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + 32 + 8 + 32 + 32 + 8 + 1 + 4 + 3 + 8 + 8 + 8 + 8 + 8 + 8 + 124 + 32 + 6 + 8 + 8
+            + 4 + MAX_FEE_SPLITS * (32 + 2 + 8) + 8 + 8,
+        seeds = [b"lottery", authority.key().as_ref(), &lottery_id.to_le_bytes()],
+        bump
+    )]
+    pub lottery: Account<'info, LotteryState>,
+
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + 32 + 8 + 8 + 8 + 8 + 1 + 8 + 3 + 124 + 8 + 8 + 32 + 8 + 8 + 32 + 32 + 8 + 1 + 8 + 8,
+        seeds = [b"round", lottery.key().as_ref(), &0u64.to_le_bytes()],
+        bump
+    )]
+    pub round: Account<'info, Round>,
+
+    #[account(
+        init,
+        payer = authority,
+        seeds = [b"token_account", authority.key().as_ref(), &lottery_id.to_le_bytes()],
+        bump,
+        token::mint = token_mint,
+        token::authority = lottery
+    )]
+    pub token_account: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: Token mint account
+    pub token_mint: AccountInfo<'info>,
 
     #[account(mut)]
     pub authority: Signer<'info>,
@@ -300,60 +1108,254 @@ pub struct Initialize<'info> {
 pub struct BuyTicket<'info> {
     #[account(
         mut,
-        seeds = [b"lottery", lottery.authority.as_ref()],
+        seeds = [b"lottery", lottery.authority.as_ref(), &lottery.lottery_id.to_le_bytes()],
         bump
     )]
     pub lottery: Account<'info, LotteryState>,
 
     #[account(
         mut,
-        seeds = [b"token_account", lottery.authority.as_ref()],
+        seeds = [b"token_account", lottery.authority.as_ref(), &lottery.lottery_id.to_le_bytes()],
         bump,
         token::mint = lottery.token_mint,
         token::authority = lottery
     )]
     pub lottery_token_account: Account<'info, TokenAccount>,
 
+    #[account(
+        mut,
+        seeds = [b"round", lottery.key().as_ref(), &lottery.current_round.to_le_bytes()],
+        bump,
+        has_one = lottery
+    )]
+    pub round: Account<'info, Round>,
+
+    #[account(
+        init,
+        payer = buyer,
+        space = 8 + 32 + 8 + 8 + 32 + 4 + (1 + 3 + 8) + 8 + 8 + 1 + 8 + 1,
+        seeds = [b"ticket", lottery.key().as_ref(), &lottery.ticket_count.to_le_bytes()],
+        bump
+    )]
+    pub ticket: Account<'info, Ticket>,
+
     #[account(mut)]
     pub buyer_token_account: Account<'info, TokenAccount>,
+    #[account(mut)]
     pub buyer: Signer<'info>,
+    pub system_program: Program<'info, System>,
     pub token_program: Program<'info, Token>,
 }
 
 #[derive(Accounts)]
-pub struct Draw<'info> {
+#[instruction(lines: Vec<LineInfo>)]
+pub struct BuyTickets<'info> {
     #[account(
         mut,
-        seeds = [b"lottery", lottery.authority.as_ref()],
+        seeds = [b"lottery", lottery.authority.as_ref(), &lottery.lottery_id.to_le_bytes()],
+        bump
+    )]
+    pub lottery: Account<'info, LotteryState>,
+
+    #[account(
+        mut,
+        seeds = [b"token_account", lottery.authority.as_ref(), &lottery.lottery_id.to_le_bytes()],
+        bump,
+        token::mint = lottery.token_mint,
+        token::authority = lottery
+    )]
+    pub lottery_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"round", lottery.key().as_ref(), &lottery.current_round.to_le_bytes()],
+        bump,
+        has_one = lottery
+    )]
+    pub round: Account<'info, Round>,
+
+    #[account(
+        init,
+        payer = buyer,
+        space = 8 + 32 + 8 + 8 + 32 + 4 + lines.len() * (1 + 3 + 8) + 8 + 8 + 1 + 8 + 1,
+        seeds = [b"ticket", lottery.key().as_ref(), &lottery.ticket_count.to_le_bytes()],
+        bump
+    )]
+    pub ticket: Account<'info, Ticket>,
+
+    #[account(mut)]
+    pub buyer_token_account: Account<'info, TokenAccount>,
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct CommitDraw<'info> {
+    #[account(
+        seeds = [b"lottery", lottery.authority.as_ref(), &lottery.lottery_id.to_le_bytes()],
         bump,
         has_one = authority
     )]
     pub lottery: Account<'info, LotteryState>,
 
-    /// CHECK: Recent blockhashes account for VRF
-    #[account(address = solana_program::sysvar::recent_blockhashes::ID)]
-    pub recent_blockhashes: AccountInfo<'info>,
+    #[account(
+        mut,
+        seeds = [b"round", lottery.key().as_ref(), &lottery.current_round.to_le_bytes()],
+        bump,
+        has_one = lottery
+    )]
+    pub round: Account<'info, Round>,
+
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct LockDraw<'info> {
+    #[account(
+        seeds = [b"lottery", lottery.authority.as_ref(), &lottery.lottery_id.to_le_bytes()],
+        bump
+    )]
+    pub lottery: Account<'info, LotteryState>,
+
+    #[account(
+        mut,
+        seeds = [b"round", lottery.key().as_ref(), &lottery.current_round.to_le_bytes()],
+        bump,
+        has_one = lottery
+    )]
+    pub round: Account<'info, Round>,
+}
+
+#[derive(Accounts)]
+pub struct Draw<'info> {
+    #[account(
+        mut,
+        seeds = [b"lottery", lottery.authority.as_ref(), &lottery.lottery_id.to_le_bytes()],
+        bump
+    )]
+    pub lottery: Account<'info, LotteryState>,
+
+    #[account(
+        mut,
+        seeds = [b"round", lottery.key().as_ref(), &lottery.current_round.to_le_bytes()],
+        bump,
+        has_one = lottery
+    )]
+    pub round: Account<'info, Round>,
+
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + 32 + 8 + 8 + 8 + 8 + 1 + 8 + 3 + 124 + 8 + 8 + 32 + 8 + 8 + 32 + 32 + 8 + 1 + 8 + 8,
+        seeds = [b"round", lottery.key().as_ref(), &(lottery.current_round + 1).to_le_bytes()],
+        bump
+    )]
+    pub next_round: Account<'info, Round>,
+
+    /// CHECK: SlotHashes sysvar holding the locked target slot
+    #[account(address = solana_program::sysvar::slot_hashes::ID)]
+    pub slot_hashes: AccountInfo<'info>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SettleTicket<'info> {
+    #[account(
+        mut,
+        seeds = [b"lottery", lottery.authority.as_ref(), &lottery.lottery_id.to_le_bytes()],
+        bump
+    )]
+    pub lottery: Account<'info, LotteryState>,
+
+    #[account(
+        mut,
+        seeds = [b"round", lottery.key().as_ref(), &ticket.round_id.to_le_bytes()],
+        bump,
+        has_one = lottery
+    )]
+    pub round: Account<'info, Round>,
+
+    #[account(
+        mut,
+        seeds = [b"ticket", lottery.key().as_ref(), &ticket.ticket_id.to_le_bytes()],
+        bump,
+        has_one = lottery
+    )]
+    pub ticket: Account<'info, Ticket>,
+}
 
+#[derive(Accounts)]
+pub struct UpdateConfig<'info> {
+    #[account(
+        mut,
+        seeds = [b"lottery", lottery.authority.as_ref(), &lottery.lottery_id.to_le_bytes()],
+        bump,
+        has_one = authority
+    )]
+    pub lottery: Account<'info, LotteryState>,
     pub authority: Signer<'info>,
 }
 
 #[derive(Accounts)]
-pub struct UpdatePrize<'info> {
+pub struct WithdrawRevenue<'info> {
     #[account(
         mut,
-        seeds = [b"lottery", lottery.authority.as_ref()],
+        seeds = [b"lottery", lottery.authority.as_ref(), &lottery.lottery_id.to_le_bytes()],
         bump,
         has_one = authority
     )]
     pub lottery: Account<'info, LotteryState>,
+
+    #[account(
+        mut,
+        seeds = [b"token_account", lottery.authority.as_ref(), &lottery.lottery_id.to_le_bytes()],
+        bump,
+        token::mint = lottery.token_mint,
+        token::authority = lottery
+    )]
+    pub lottery_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = authority_token_account.mint == lottery.token_mint @ LotteryError::InvalidTokenMint
+    )]
+    pub authority_token_account: Account<'info, TokenAccount>,
+
     pub authority: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct SweepFees<'info> {
+    #[account(
+        mut,
+        seeds = [b"lottery", lottery.authority.as_ref(), &lottery.lottery_id.to_le_bytes()],
+        bump
+    )]
+    pub lottery: Account<'info, LotteryState>,
+
+    #[account(
+        mut,
+        seeds = [b"token_account", lottery.authority.as_ref(), &lottery.lottery_id.to_le_bytes()],
+        bump,
+        token::mint = lottery.token_mint,
+        token::authority = lottery
+    )]
+    pub lottery_token_account: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
 }
 
 #[derive(Accounts)]
 pub struct WithdrawSol<'info> {
     #[account(
         mut,
-        seeds = [b"lottery", lottery.authority.as_ref()],
+        seeds = [b"lottery", lottery.authority.as_ref(), &lottery.lottery_id.to_le_bytes()],
         bump,
         has_one = authority
     )]
@@ -367,7 +1369,7 @@ pub struct WithdrawSol<'info> {
 pub struct TransferToken<'info> {
     #[account(
         mut,
-        seeds = [b"lottery", authority.key().as_ref()],
+        seeds = [b"lottery", authority.key().as_ref(), &lottery.lottery_id.to_le_bytes()],
         bump,
         has_one = authority,
     )]
@@ -375,7 +1377,15 @@ pub struct TransferToken<'info> {
 
     #[account(
         mut,
-        seeds = [b"token_account", authority.key().as_ref()],
+        seeds = [b"round", lottery.key().as_ref(), &round.round_id.to_le_bytes()],
+        bump,
+        has_one = lottery
+    )]
+    pub round: Account<'info, Round>,
+
+    #[account(
+        mut,
+        seeds = [b"token_account", authority.key().as_ref(), &lottery.lottery_id.to_le_bytes()],
         bump,
         token::mint = mint.key(),
         token::authority = lottery,
@@ -385,19 +1395,38 @@ pub struct TransferToken<'info> {
     /// CHECK: Token mint account, verified in the token_account constraint
     pub mint: AccountInfo<'info>,
 
+    #[account(mut)]
     pub authority: Signer<'info>,
     pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct LineInfo {
+    pub bet_type: BetType,
+    pub numbers: [u8; NUMBERS_COUNT],
+    pub multiplier: u32,
+    pub quick_pick: bool,
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize, Clone)]
 pub struct TransferInfo {
     pub recipient: Pubkey,
+    pub ticket_id: u64,
     pub amount: u64,
 }
 
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct FeeSplitInfo {
+    pub recipient: Pubkey,
+    pub bps: u16,
+}
+
 #[event]
 pub struct LotteryInitialized {
     pub authority: Pubkey,
+    pub lottery_id: u64,
     pub token_mint: Pubkey,
     pub min_purchase_amount: u32,
 }
@@ -405,21 +1434,71 @@ pub struct LotteryInitialized {
 #[event]
 pub struct TicketPurchased {
     pub buyer: Pubkey,
+    pub round_id: u64,
+    pub ticket_id: u64,
+    pub bet_type: BetType,
     pub numbers: [u8; NUMBERS_COUNT],
     pub amount: u64,
+    pub quick_pick: bool,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct TicketLinesPurchased {
+    pub buyer: Pubkey,
+    pub round_id: u64,
+    pub ticket_id: u64,
+    pub lines: Vec<LineInfo>,
+    pub amount: u64,
     pub timestamp: i64,
 }
 
+#[event]
+pub struct DrawCommitted {
+    pub round_id: u64,
+    pub commitment: [u8; 32],
+    pub commit_slot: u64,
+}
+
+#[event]
+pub struct DrawLocked {
+    pub round_id: u64,
+    pub target_slot: u64,
+}
+
 #[event]
 pub struct DrawResult {
+    pub round_id: u64,
     pub numbers: [u8; NUMBERS_COUNT],
     pub draw_time: i64,
 }
 
 #[event]
-pub struct PrizeAmountUpdated {
-    pub amount: u64,
-    pub draw_time: i64,
+pub struct RoundVoided {
+    pub round_id: u64,
+    pub ticket_count: u64,
+    pub refund_amount: u64,
+}
+
+#[event]
+pub struct TicketSettled {
+    pub round_id: u64,
+    pub ticket_id: u64,
+    pub buyer: Pubkey,
+    pub prize_amount: u64,
+}
+
+#[event]
+pub struct PaytableUpdated {
+    pub paytable: Paytable,
+}
+
+#[event]
+pub struct ScheduleUpdated {
+    pub draw_interval: i64,
+    pub draw_offset: i64,
+    pub draw_window: i64,
+    pub lock_duration: i64,
 }
 
 #[event]
@@ -429,6 +1508,13 @@ pub struct TokenDrawTransfer {
     pub remaining_prize: u64,
 }
 
+#[event]
+pub struct PayoutSkipped {
+    pub ticket_id: u64,
+    pub recipient: Pubkey,
+    pub amount: u64,
+}
+
 #[event]
 pub struct BatchTransferCompleted {
     pub total_amount: u64,
@@ -437,6 +1523,32 @@ pub struct BatchTransferCompleted {
     pub timestamp: i64,
 }
 
+#[event]
+pub struct RevenueSplitUpdated {
+    pub prize_bps: u16,
+    pub reserve_bps: u16,
+    pub house_bps: u16,
+}
+
+#[event]
+pub struct RevenueWithdrawn {
+    pub amount: u64,
+    pub house_revenue: u64,
+    pub recipient: Pubkey,
+}
+
+#[event]
+pub struct FeeSplitsUpdated {
+    pub fee_splits: Vec<FeeSplitInfo>,
+    pub released_fees: u64,
+}
+
+#[event]
+pub struct FeesSwept {
+    pub recipients: Vec<Pubkey>,
+    pub amounts: Vec<u64>,
+}
+
 #[event]
 pub struct SolWithdrawn {
     pub amount: u64,
@@ -450,9 +1562,9 @@ pub enum LotteryError {
     Locked,
     #[msg("Insufficient token amount for ticket purchase")]
     InsufficientAmount,
-    #[msg("Invalid ticket numbers: must be between 1 and 33")]
+    #[msg("Invalid ticket numbers for the bet type: digits 0-9, or a sum of 0-27")]
     InvalidTicketNumbers,
-    #[msg("Invalid draw time - must be at the start of each hour")]
+    #[msg("Invalid draw time - outside the scheduled draw window")]
     InvalidDrawTime,
     #[msg("Insufficient balance for withdrawal")]
     InsufficientBalance,
@@ -460,7 +1572,7 @@ pub enum LotteryError {
     InvalidTokenMint,
     #[msg("Must wait minimum interval between draws")]
     DrawTooEarly,
-    #[msg("Already drawn this hour")]
+    #[msg("Already drawn this period")]
     AlreadyDrawn,
     #[msg("Prize update window is closed")]
     PrizeUpdateWindowClosed,
@@ -472,54 +1584,753 @@ pub enum LotteryError {
     InvalidPrizeAmount,
     #[msg("Cannot buy tickets during draw window")]
     DrawWindowActive,
+    #[msg("Round has not been drawn yet")]
+    RoundNotDrawn,
+    #[msg("Ticket has already been settled")]
+    TicketAlreadySettled,
+    #[msg("Invalid paytable: a bet type would pay out more than it takes in")]
+    InvalidPaytable,
+    #[msg("Draw commitment already submitted for this round")]
+    AlreadyCommitted,
+    #[msg("Draw commitment must not be empty")]
+    InvalidCommitment,
+    #[msg("No draw commitment submitted for this round")]
+    DrawNotCommitted,
+    #[msg("Revealed secret does not match the commitment")]
+    InvalidReveal,
+    #[msg("Draw target slot has not been reached yet")]
+    RevealTooEarly,
+    #[msg("Invalid draw schedule")]
+    InvalidSchedule,
+    #[msg("A ticket must have between 1 and 32 lines")]
+    InvalidLineCount,
+    #[msg("Draw target slot has not been locked")]
+    DrawNotLocked,
+    #[msg("Draw target slot is already locked")]
+    DrawAlreadyLocked,
+    #[msg("Draw target slot hash is no longer in the SlotHashes sysvar")]
+    DrawExpired,
+    #[msg(
+        "Each transfer needs its ticket and token account, plus the wallet when creating accounts"
+    )]
+    RecipientCountMismatch,
+    #[msg("Transfer amounts do not add up to the total amount")]
+    TransferTotalMismatch,
+    #[msg("Recipient appears more than once in the batch")]
+    DuplicateRecipient,
+    #[msg("Recipient account is not a token account")]
+    InvalidRecipientAccount,
+    #[msg("Recipient token account is not owned by the listed recipient")]
+    RecipientOwnerMismatch,
+    #[msg("Payout ticket does not belong to this lottery or batch entry")]
+    InvalidPayoutTicket,
+    #[msg("Ticket has not been settled yet")]
+    TicketNotSettled,
+    #[msg("Payout amount does not match the settled ticket prize")]
+    PayoutAmountMismatch,
+    #[msg("Ticket appears more than once in the batch")]
+    DuplicatePayoutTicket,
+    #[msg("Recipient account is not the recipient's associated token account")]
+    InvalidAssociatedTokenAccount,
+    #[msg("Revenue split must add up to 100%")]
+    InvalidRevenueSplit,
+    #[msg("Withdrawal exceeds the accrued house revenue")]
+    InsufficientRevenue,
+    #[msg("Withdrawal would leave prize liabilities uncovered")]
+    LiabilitiesUncovered,
+    #[msg("Prize share is below the paytable's expected payout")]
+    PrizeShareTooLow,
+    #[msg("Drawn tickets must be settled before revenue is withdrawn")]
+    TicketsUnsettled,
+    #[msg("At most 4 fee splits are allowed")]
+    TooManyFeeSplits,
+    #[msg("Fee splits need a non-zero share and distinct recipients")]
+    InvalidFeeSplit,
+    #[msg("Fee splits plus the prize and reserve shares exceed 100%")]
+    FeeSplitTooHigh,
+    #[msg("Fee recipient account does not match the fee split table")]
+    InvalidFeeRecipient,
+    #[msg("Draw target slot hash can still be revealed")]
+    RevealStillPossible,
 }
 
 impl LotteryState {
+    pub fn fee_bps(&self) -> u64 {
+        self.fee_splits.iter().map(|split| split.bps as u64).sum()
+    }
+
+    // Installs a new fee table. Fees a dropped recipient never swept go back to
+    // house revenue, so a dead recipient account cannot pin the table.
+    pub fn replace_fee_splits(&mut self, fee_splits: &[FeeSplitInfo]) -> Option<u64> {
+        let (splits, released) = carry_over_fees(&self.fee_splits, fee_splits)?;
+        self.house_revenue = self.house_revenue.checked_add(released)?;
+        self.fee_splits = splits;
+        Some(released)
+    }
+
+    pub fn accrued_fees(&self) -> u64 {
+        self.fee_splits.iter().map(|split| split.accrued).sum()
+    }
+
+    pub fn open_sales(&mut self, current_time: i64) -> Result<()> {
+        require!(
+            !self.is_in_draw_window(current_time),
+            LotteryError::DrawWindowActive
+        );
+
+        if self.is_locked {
+            let time_since_last_draw = current_time - self.last_draw_time;
+            require!(
+                time_since_last_draw > self.schedule.lock_duration,
+                LotteryError::Locked
+            );
+            self.is_locked = false;
+        }
+        Ok(())
+    }
+
     pub fn is_in_draw_window(&self, current_time: i64) -> bool {
-        (current_time / SECONDS_PER_MINUTE) % MINUTES_PER_HOUR <= DRAW_WINDOW_MINUTES
+        let schedule = &self.schedule;
+        (current_time - schedule.draw_offset).rem_euclid(schedule.draw_interval)
+            < schedule.draw_window
     }
 
     pub fn can_draw(&self, current_time: i64) -> bool {
         let time_since_last_draw = current_time - self.last_draw_time;
-        time_since_last_draw >= MIN_DRAW_INTERVAL && !self.is_locked
+        let min_draw_interval = self.schedule.draw_interval - self.schedule.draw_window;
+        time_since_last_draw >= min_draw_interval && !self.is_locked
     }
 }
 
-fn validate_ticket_numbers(numbers: &[u8; NUMBERS_COUNT]) -> bool {
-    numbers
+impl DrawSchedule {
+    pub fn is_valid(&self) -> bool {
+        self.draw_interval > 0
+            && (0..self.draw_interval).contains(&self.draw_offset)
+            && self.draw_window > 0
+            && self.lock_duration >= 0
+            && self
+                .draw_window
+                .checked_add(self.lock_duration)
+                .is_some_and(|busy| busy < self.draw_interval)
+    }
+}
+
+impl RevenueSplit {
+    pub fn is_valid(&self) -> bool {
+        self.prize_bps as u64 + self.reserve_bps as u64 + self.house_bps as u64 == BPS_DENOMINATOR
+    }
+
+    pub fn shares(&self, amount: u64) -> (u64, u64) {
+        let share = |bps: u16| (amount as u128 * bps as u128 / BPS_DENOMINATOR as u128) as u64;
+        (share(self.reserve_bps), share(self.house_bps))
+    }
+
+    pub fn covers_fees(&self, fee_bps: u64) -> bool {
+        self.prize_bps as u64 + self.reserve_bps as u64 + fee_bps <= BPS_DENOMINATOR
+    }
+}
+
+impl Paytable {
+    pub fn multiplier(
+        &self,
+        bet_type: BetType,
+        numbers: &[u8; NUMBERS_COUNT],
+        draw_numbers: &[u8; NUMBERS_COUNT],
+    ) -> u32 {
+        let won = match bet_type {
+            BetType::Straight => numbers == draw_numbers,
+            BetType::Group3 | BetType::Group6 => sorted(numbers) == sorted(draw_numbers),
+            BetType::Sum => numbers[0] as usize == digit_sum(draw_numbers),
+        };
+        if !won {
+            return 0;
+        }
+
+        match bet_type {
+            BetType::Straight => self.straight,
+            BetType::Group3 => self.group3,
+            BetType::Group6 => self.group6,
+            BetType::Sum => self.sum[numbers[0] as usize],
+        }
+    }
+
+    // No bet type may return more than its stake in expectation.
+    pub fn is_valid(&self) -> bool {
+        self.max_rtp_bps() <= BPS_DENOMINATOR
+    }
+
+    // Expected return of the most generous bet, in basis points of the stake.
+    pub fn max_rtp_bps(&self) -> u64 {
+        let mut sum_ways = [0u64; MAX_SUM + 1];
+        for combo in 0..TOTAL_COMBINATIONS {
+            let digits = [
+                (combo / 100) as u8,
+                (combo / 10 % 10) as u8,
+                (combo % 10) as u8,
+            ];
+            sum_ways[digit_sum(&digits)] += 1;
+        }
+
+        let rtp_bps = |multiplier: u32, ways: u64| {
+            (multiplier as u64 * ways * BPS_DENOMINATOR).div_ceil(TOTAL_COMBINATIONS)
+        };
+        self.sum
+            .iter()
+            .zip(sum_ways)
+            .map(|(&multiplier, ways)| rtp_bps(multiplier, ways))
+            .chain([
+                rtp_bps(self.straight, 1),
+                rtp_bps(self.group3, 3),
+                rtp_bps(self.group6, 6),
+            ])
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+impl FeeSplit {
+    pub fn fee(&self, amount: u64) -> u64 {
+        (amount as u128 * self.bps as u128 / BPS_DENOMINATOR as u128) as u64
+    }
+}
+
+// Adds each recipient's cut of `amount` to its balance and returns the total.
+fn accrue_fees(fee_splits: &mut [FeeSplit], amount: u64) -> Option<u64> {
+    fee_splits.iter_mut().try_fold(0u64, |total, split| {
+        let fee = split.fee(amount);
+        split.accrued = split.accrued.checked_add(fee)?;
+        total.checked_add(fee)
+    })
+}
+
+// Builds the new fee table, carrying over what kept recipients are still owed,
+// and returns the unswept total of recipients that were dropped.
+fn carry_over_fees(
+    current: &[FeeSplit],
+    fee_splits: &[FeeSplitInfo],
+) -> Option<(Vec<FeeSplit>, u64)> {
+    let accrued = |recipient: Pubkey| {
+        current
+            .iter()
+            .find(|split| split.recipient == recipient)
+            .map(|split| split.accrued)
+    };
+    let splits = fee_splits
         .iter()
-        .all(|&num| num >= MIN_NUMBER && num <= MAX_NUMBER)
+        .map(|split| FeeSplit {
+            recipient: split.recipient,
+            bps: split.bps,
+            accrued: accrued(split.recipient).unwrap_or(0),
+        })
+        .collect();
+    let released = current
+        .iter()
+        .filter(|split| {
+            fee_splits
+                .iter()
+                .all(|kept| kept.recipient != split.recipient)
+        })
+        .try_fold(0u64, |total, split| total.checked_add(split.accrued))?;
+    Some((splits, released))
 }
 
-fn generate_vrf_random_number(
-    timestamp: i64,
-    recent_blockhashes: &[u8],
-    lottery_account: &AccountInfo,
-    uuid: &str,
-) -> Result<[u8; 32]> {
-    let mut data = Vec::with_capacity(512);
-    data.extend_from_slice(uuid.as_bytes());
-    data.extend_from_slice(&timestamp.to_le_bytes());
-    data.extend_from_slice(recent_blockhashes);
-    data.extend_from_slice(&lottery_account.data.borrow());
-    data.extend_from_slice(&lottery_account.lamports().to_le_bytes());
+// Splits a prize between what is left of the round's prize share, the reserve
+// and house revenue, in that order; whatever none of them covers stays owed.
+fn fund_prize(prize: u64, pool_left: u64, reserve: u64, house: u64) -> (u64, u64, u64) {
+    let from_pool = prize.min(pool_left);
+    let from_reserve = (prize - from_pool).min(reserve);
+    let from_house = (prize - from_pool - from_reserve).min(house);
+    (from_pool, from_reserve, from_house)
+}
 
-    if let Ok(clock) = Clock::get() {
-        data.extend_from_slice(&clock.slot.to_le_bytes());
+fn validate_ticket_numbers(bet_type: BetType, numbers: &[u8; NUMBERS_COUNT]) -> bool {
+    let digits_valid = numbers
+        .iter()
+        .all(|num| (MIN_NUMBER..=MAX_NUMBER).contains(num));
+    let [a, b, c] = sorted(numbers);
+
+    match bet_type {
+        BetType::Straight => digits_valid,
+        BetType::Group3 => digits_valid && (a == b) != (b == c),
+        BetType::Group6 => digits_valid && a != b && b != c,
+        BetType::Sum => numbers[0] as usize <= MAX_SUM && numbers[1] == 0 && numbers[2] == 0,
     }
+}
+
+fn enter_ticket(
+    lottery: &mut Account<LotteryState>,
+    round: &mut Account<Round>,
+    ticket: &mut Account<Ticket>,
+    amount: u64,
+    current_time: i64,
+) -> Result<()> {
+    ticket.lottery = lottery.key();
+    ticket.round_id = lottery.current_round;
+    ticket.ticket_id = lottery.ticket_count;
+    ticket.amount = amount;
+    ticket.purchase_time = current_time;
+
+    let (reserve_share, mut house_share) = lottery.revenue_split.shares(amount);
+    let prize_share = amount
+        .checked_sub(reserve_share)
+        .and_then(|rest| rest.checked_sub(house_share))
+        .ok_or(LotteryError::ArithmeticError)?;
+    // Partner fees are carved out of the house share.
+    let fees = accrue_fees(&mut lottery.fee_splits, amount).ok_or(LotteryError::ArithmeticError)?;
+    house_share = house_share
+        .checked_sub(fees)
+        .ok_or(LotteryError::ArithmeticError)?;
+    lottery.reserve_pool = lottery
+        .reserve_pool
+        .checked_add(reserve_share)
+        .ok_or(LotteryError::ArithmeticError)?;
+    lottery.house_revenue = lottery
+        .house_revenue
+        .checked_add(house_share)
+        .ok_or(LotteryError::ArithmeticError)?;
+
+    round.ticket_count = round
+        .ticket_count
+        .checked_add(1)
+        .ok_or(LotteryError::ArithmeticError)?;
+    round.total_amount = round
+        .total_amount
+        .checked_add(amount)
+        .ok_or(LotteryError::ArithmeticError)?;
+    round.prize_pool = round
+        .prize_pool
+        .checked_add(prize_share)
+        .ok_or(LotteryError::ArithmeticError)?;
+    lottery.open_prize_pool = lottery
+        .open_prize_pool
+        .checked_add(prize_share)
+        .ok_or(LotteryError::ArithmeticError)?;
+
+    lottery.ticket_count = lottery
+        .ticket_count
+        .checked_add(1)
+        .ok_or(LotteryError::ArithmeticError)?;
+
+    Ok(())
+}
+
+fn sorted(numbers: &[u8; NUMBERS_COUNT]) -> [u8; NUMBERS_COUNT] {
+    let mut sorted = *numbers;
+    sorted.sort_unstable();
+    sorted
+}
+
+fn digit_sum(numbers: &[u8; NUMBERS_COUNT]) -> usize {
+    numbers.iter().map(|&num| num as usize).sum()
+}
+
+fn find_draw_slot_hash(slot_hashes: &[u8], target_slot: u64) -> Option<(u64, [u8; 32])> {
+    let count = u64::from_le_bytes(slot_hashes.get(..8)?.try_into().ok()?) as usize;
+    let mut found = None;
 
-    let mut final_hash = hash(&data).to_bytes();
-    for _ in 0..3 {
-        final_hash = hash(&final_hash).to_bytes();
+    // Newest entries come first. A skipped target falls through to the next
+    // recorded slot, but only when an older entry shows nothing was evicted.
+    for entry in slot_hashes[8..].chunks_exact(40).take(count) {
+        let slot = u64::from_le_bytes(entry[..8].try_into().unwrap());
+        if slot < target_slot {
+            return found;
+        }
+        found = Some((slot, entry[8..].try_into().unwrap()));
+        if slot == target_slot {
+            return found;
+        }
     }
 
-    Ok(final_hash)
+    None
+}
+
+// Validates a batch entry against its ticket; returns false when it was already paid.
+fn check_payout(
+    lottery: &Pubkey,
+    round: &Round,
+    ticket: &Ticket,
+    transfer: &TransferInfo,
+) -> Result<bool> {
+    require!(
+        ticket.lottery == *lottery
+            && ticket.round_id == round.round_id
+            && ticket.ticket_id == transfer.ticket_id,
+        LotteryError::InvalidPayoutTicket
+    );
+    require!(
+        ticket.buyer == transfer.recipient,
+        LotteryError::RecipientOwnerMismatch
+    );
+    require!(ticket.is_settled, LotteryError::TicketNotSettled);
+    require!(
+        transfer.amount == ticket.prize_amount,
+        LotteryError::PayoutAmountMismatch
+    );
+    Ok(!ticket.is_paid)
+}
+
+// Accounts are only created at the recipient's associated token address.
+fn check_recipient_ata(
+    wallet: &Pubkey,
+    token_account: &Pubkey,
+    recipient: &Pubkey,
+    mint: &Pubkey,
+) -> Result<()> {
+    require!(
+        wallet == recipient
+            && *token_account == associated_token::get_associated_token_address(recipient, mint),
+        LotteryError::InvalidAssociatedTokenAccount
+    );
+    Ok(())
+}
+
+fn generate_draw_random_number(
+    secret: &[u8; 32],
+    slot_hash: &[u8; 32],
+    lottery: &Pubkey,
+    round_id: u64,
+) -> [u8; 32] {
+    let mut data = Vec::with_capacity(104);
+    data.extend_from_slice(secret);
+    data.extend_from_slice(slot_hash);
+    data.extend_from_slice(lottery.as_ref());
+    data.extend_from_slice(&round_id.to_le_bytes());
+    hash(&data).to_bytes()
+}
+
+fn quick_pick_seed(
+    lottery: &Pubkey,
+    ticket_id: u64,
+    buyer: &Pubkey,
+    clock: &Clock,
+    line: usize,
+) -> [u8; 32] {
+    // Quick picks only need to look random; every pick has the same odds of winning.
+    let mut data = Vec::with_capacity(92);
+    data.extend_from_slice(lottery.as_ref());
+    data.extend_from_slice(&ticket_id.to_le_bytes());
+    data.extend_from_slice(buyer.as_ref());
+    data.extend_from_slice(&clock.slot.to_le_bytes());
+    data.extend_from_slice(&clock.unix_timestamp.to_le_bytes());
+    data.extend_from_slice(&(line as u32).to_le_bytes());
+    hash(&data).to_bytes()
+}
+
+fn quick_pick_numbers(bet_type: BetType, seed: &[u8; 32]) -> Option<[u8; NUMBERS_COUNT]> {
+    // Redraw until the digits fit the bet type; about a quarter of draws suit group 3.
+    (0..=u8::MAX).find_map(|attempt| {
+        let digits = convert_random_to_3d_numbers(&hashv(&[seed, &[attempt]]).to_bytes());
+        let numbers = match bet_type {
+            BetType::Sum => [digit_sum(&digits) as u8, 0, 0],
+            _ => digits,
+        };
+        validate_ticket_numbers(bet_type, &numbers).then_some(numbers)
+    })
+}
+
+// Byte stream over a draw hash, extended with hash(seed, counter) once the
+// first 32 bytes run out.
+struct RandomStream {
+    seed: [u8; 32],
+    block: [u8; 32],
+    index: usize,
+    counter: u32,
+}
+
+impl RandomStream {
+    fn new(seed: &[u8; 32]) -> Self {
+        Self {
+            seed: *seed,
+            block: *seed,
+            index: 0,
+            counter: 0,
+        }
+    }
+
+    fn next_byte(&mut self) -> u8 {
+        if self.index == self.block.len() {
+            self.counter += 1;
+            self.block = hashv(&[&self.seed, &self.counter.to_le_bytes()]).to_bytes();
+            self.index = 0;
+        }
+        self.index += 1;
+        self.block[self.index - 1]
+    }
+
+    // Uniform in 0..n: bytes past the largest multiple of n are rejected.
+    fn uniform(&mut self, n: u8) -> u8 {
+        let limit = 256 - 256 % n as u16;
+        loop {
+            let byte = self.next_byte();
+            if (byte as u16) < limit {
+                return byte % n;
+            }
+        }
+    }
 }
 
 fn convert_random_to_3d_numbers(random_value: &[u8; 32]) -> [u8; NUMBERS_COUNT] {
+    let mut stream = RandomStream::new(random_value);
     let mut numbers = [0u8; NUMBERS_COUNT];
-    for i in 0..NUMBERS_COUNT {
-        numbers[i] = (random_value[i] % MAX_NUMBER) + 1;
+    for number in numbers.iter_mut() {
+        *number = MIN_NUMBER + stream.uniform(MAX_NUMBER - MIN_NUMBER + 1);
     }
     numbers
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DRAWS: u64 = 2_000_000;
+
+    fn draws() -> impl Iterator<Item = [u8; NUMBERS_COUNT]> {
+        (0..DRAWS).map(|i| convert_random_to_3d_numbers(&hash(&i.to_le_bytes()).to_bytes()))
+    }
+
+    fn chi_squared(observed: &[u64], expected: f64) -> f64 {
+        observed
+            .iter()
+            .map(|&count| (count as f64 - expected).powi(2) / expected)
+            .sum()
+    }
+
+    // Wilson-Hilferty approximation of the chi-squared quantile at p = 1e-6.
+    fn critical_value(degrees_of_freedom: usize) -> f64 {
+        let k = degrees_of_freedom as f64;
+        let z = 4.753;
+        k * (1.0 - 2.0 / (9.0 * k) + z * (2.0 / (9.0 * k)).sqrt()).powi(3)
+    }
+
+    #[test]
+    fn exhausted_hash_falls_back_to_expansion() {
+        // Bytes from 250 up are rejected, so every digit comes from the expansion.
+        let numbers = convert_random_to_3d_numbers(&[0xff; 32]);
+        assert!(validate_ticket_numbers(BetType::Straight, &numbers));
+    }
+
+    #[test]
+    fn digits_are_uniform_in_every_position() {
+        let mut counts = [[0u64; 10]; NUMBERS_COUNT];
+        for numbers in draws() {
+            for (position, &digit) in numbers.iter().enumerate() {
+                counts[position][digit as usize] += 1;
+            }
+        }
+
+        for position_counts in counts.iter() {
+            assert!(chi_squared(position_counts, DRAWS as f64 / 10.0) < critical_value(9));
+        }
+    }
+
+    #[test]
+    fn every_combination_is_equally_likely() {
+        let mut counts = vec![0u64; TOTAL_COMBINATIONS as usize];
+        for numbers in draws() {
+            let combo = numbers[0] as usize * 100 + numbers[1] as usize * 10 + numbers[2] as usize;
+            counts[combo] += 1;
+        }
+
+        let expected = DRAWS as f64 / TOTAL_COMBINATIONS as f64;
+        assert!(counts.iter().all(|&count| count > 0));
+        assert!(chi_squared(&counts, expected) < critical_value(999));
+    }
+
+    #[test]
+    fn payouts_match_settled_prizes_and_skip_paid_tickets() {
+        let lottery = Pubkey::new_unique();
+        let buyer = Pubkey::new_unique();
+        let round = Round {
+            lottery,
+            round_id: 4,
+            ..Round::default()
+        };
+        let mut ticket = Ticket {
+            lottery,
+            buyer,
+            round_id: 4,
+            ticket_id: 9,
+            prize_amount: 500,
+            ..Ticket::default()
+        };
+        let transfer = TransferInfo {
+            recipient: buyer,
+            ticket_id: 9,
+            amount: 500,
+        };
+
+        let err = check_payout(&lottery, &round, &ticket, &transfer).unwrap_err();
+        assert_eq!(err, LotteryError::TicketNotSettled.into());
+
+        ticket.is_settled = true;
+        assert!(check_payout(&lottery, &round, &ticket, &transfer).unwrap());
+
+        let inflated = TransferInfo {
+            amount: 501,
+            ..transfer.clone()
+        };
+        let err = check_payout(&lottery, &round, &ticket, &inflated).unwrap_err();
+        assert_eq!(err, LotteryError::PayoutAmountMismatch.into());
+
+        let other_ticket = TransferInfo {
+            ticket_id: 10,
+            ..transfer.clone()
+        };
+        let err = check_payout(&lottery, &round, &ticket, &other_ticket).unwrap_err();
+        assert_eq!(err, LotteryError::InvalidPayoutTicket.into());
+
+        ticket.is_paid = true;
+        assert!(!check_payout(&lottery, &round, &ticket, &transfer).unwrap());
+    }
+
+    #[test]
+    fn payout_accounts_are_created_at_the_associated_address() {
+        let recipient = Pubkey::new_unique();
+        let mint = Pubkey::new_unique();
+        let (ata, _) = Pubkey::find_program_address(
+            &[recipient.as_ref(), token::ID.as_ref(), mint.as_ref()],
+            &associated_token::ID,
+        );
+        assert!(check_recipient_ata(&recipient, &ata, &recipient, &mint).is_ok());
+
+        let other_mint =
+            associated_token::get_associated_token_address(&recipient, &Pubkey::new_unique());
+        let err = check_recipient_ata(&recipient, &other_mint, &recipient, &mint).unwrap_err();
+        assert_eq!(err, LotteryError::InvalidAssociatedTokenAccount.into());
+
+        let err = check_recipient_ata(&Pubkey::new_unique(), &ata, &recipient, &mint).unwrap_err();
+        assert_eq!(err, LotteryError::InvalidAssociatedTokenAccount.into());
+    }
+
+    #[test]
+    fn revenue_split_shares_round_down_and_leave_room_for_fees() {
+        let split = RevenueSplit {
+            prize_bps: 6000,
+            reserve_bps: 1500,
+            house_bps: 2500,
+        };
+        assert!(split.is_valid());
+        assert!(!RevenueSplit {
+            house_bps: 2499,
+            ..split
+        }
+        .is_valid());
+        assert!(!RevenueSplit::default().is_valid());
+
+        // 15% and 25% of 999, rounded down; the prize share keeps the remainder.
+        assert_eq!(split.shares(999), (149, 249));
+        // Shares are computed in u128, so the full u64 range does not overflow.
+        assert_eq!(
+            split.shares(u64::MAX),
+            (2_767_011_611_056_432_742, 4_611_686_018_427_387_903)
+        );
+
+        // Partner fees come out of the house share only.
+        assert!(split.covers_fees(2500));
+        assert!(!split.covers_fees(2501));
+    }
+
+    #[test]
+    fn each_bet_type_pays_only_its_own_match() {
+        let paytable = DEFAULT_PAYTABLE;
+        let draw = [3, 5, 3];
+
+        assert_eq!(
+            paytable.multiplier(BetType::Straight, &[3, 5, 3], &draw),
+            520
+        );
+        assert_eq!(paytable.multiplier(BetType::Straight, &[3, 3, 5], &draw), 0);
+
+        assert_eq!(paytable.multiplier(BetType::Group3, &[5, 3, 3], &draw), 173);
+        assert_eq!(paytable.multiplier(BetType::Group3, &[5, 5, 3], &draw), 0);
+
+        let draw = [7, 1, 4];
+        assert_eq!(paytable.multiplier(BetType::Group6, &[1, 4, 7], &draw), 86);
+        assert_eq!(paytable.multiplier(BetType::Group6, &[1, 4, 8], &draw), 0);
+
+        assert_eq!(paytable.multiplier(BetType::Sum, &[12, 0, 0], &draw), 7);
+        assert_eq!(paytable.multiplier(BetType::Sum, &[13, 0, 0], &draw), 0);
+        assert_eq!(
+            paytable.multiplier(BetType::Sum, &[0, 0, 0], &[0, 0, 0]),
+            520
+        );
+        assert_eq!(
+            paytable.multiplier(BetType::Sum, &[27, 0, 0], &[9, 9, 9]),
+            520
+        );
+    }
+
+    #[test]
+    fn ticket_numbers_must_fit_their_bet_type() {
+        assert!(validate_ticket_numbers(BetType::Straight, &[0, 0, 0]));
+        assert!(validate_ticket_numbers(BetType::Straight, &[9, 9, 9]));
+        assert!(!validate_ticket_numbers(BetType::Straight, &[1, 10, 2]));
+
+        assert!(validate_ticket_numbers(BetType::Group3, &[1, 1, 2]));
+        assert!(validate_ticket_numbers(BetType::Group3, &[2, 1, 2]));
+        assert!(!validate_ticket_numbers(BetType::Group3, &[0, 0, 0]));
+        assert!(!validate_ticket_numbers(BetType::Group3, &[1, 2, 3]));
+
+        assert!(validate_ticket_numbers(BetType::Group6, &[1, 2, 3]));
+        assert!(!validate_ticket_numbers(BetType::Group6, &[1, 1, 2]));
+        assert!(!validate_ticket_numbers(BetType::Group6, &[2, 1, 2]));
+
+        assert!(validate_ticket_numbers(BetType::Sum, &[0, 0, 0]));
+        assert!(validate_ticket_numbers(BetType::Sum, &[27, 0, 0]));
+        assert!(!validate_ticket_numbers(BetType::Sum, &[28, 0, 0]));
+        assert!(!validate_ticket_numbers(BetType::Sum, &[12, 1, 0]));
+    }
+
+    #[test]
+    fn default_paytable_fits_its_expected_payout() {
+        // Sums 13 and 14 come up 75 times in 1000 and pay 7x, returning 52.5%.
+        assert_eq!(DEFAULT_PAYTABLE.max_rtp_bps(), 5250);
+        assert!(DEFAULT_PAYTABLE.is_valid());
+
+        let generous = Paytable {
+            group6: 167,
+            ..DEFAULT_PAYTABLE
+        };
+        // Group 6 hits 6 of the 1000 draws, so 167x returns 100.2%.
+        assert_eq!(generous.max_rtp_bps(), 10020);
+        assert!(!generous.is_valid());
+    }
+
+    #[test]
+    fn prizes_beyond_the_round_share_fall_to_reserve_then_house() {
+        assert_eq!(fund_prize(400, 1000, 50, 50), (400, 0, 0));
+        assert_eq!(fund_prize(400, 200, 150, 500), (200, 150, 50));
+        assert_eq!(fund_prize(400, 0, 100, 100), (0, 100, 100));
+        assert_eq!(fund_prize(0, 0, 0, 0), (0, 0, 0));
+    }
+
+    #[test]
+    fn dropped_fee_recipients_release_to_house_revenue() {
+        let (kept, dropped) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let mut lottery = LotteryState {
+            house_revenue: 1000,
+            fee_splits: vec![
+                FeeSplit {
+                    recipient: kept,
+                    bps: 100,
+                    accrued: 30,
+                },
+                FeeSplit {
+                    recipient: dropped,
+                    bps: 100,
+                    accrued: 20,
+                },
+            ],
+            ..LotteryState::default()
+        };
+
+        let released = lottery.replace_fee_splits(&[FeeSplitInfo {
+            recipient: kept,
+            bps: 200,
+        }]);
+        assert_eq!(released, Some(20));
+        assert_eq!(lottery.house_revenue, 1020);
+        assert_eq!(lottery.fee_splits.len(), 1);
+        assert_eq!(lottery.fee_splits[0].accrued, 30);
+    }
+}